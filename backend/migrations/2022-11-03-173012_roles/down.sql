@@ -0,0 +1,3 @@
+alter table users
+    drop column school_id,
+    drop column role;
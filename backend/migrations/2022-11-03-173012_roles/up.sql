@@ -0,0 +1,4 @@
+alter table users
+    add column role varchar not null default 'student',
+    add column school_id uuid,
+    add foreign key (school_id) references schools(id);
@@ -0,0 +1,3 @@
+alter table sessions
+    drop column remember,
+    drop column last_seen;
@@ -0,0 +1,3 @@
+alter table sessions
+    add column last_seen timestamp not null default now(),
+    add column remember boolean not null default false;
@@ -0,0 +1,11 @@
+create table user_tokens
+(
+    id uuid not null default gen_random_uuid() primary key,
+    user_id uuid not null,
+    purpose varchar not null,
+    token_hash varchar not null unique,
+    created_at timestamp not null default now(),
+    expires_at timestamp not null,
+    used_at timestamp,
+    foreign key (user_id) references users(id)
+);
@@ -0,0 +1,5 @@
+alter table users
+    add column verified_at timestamp;
+
+-- accounts created before verification existed are trusted
+update users set verified_at = now();
@@ -0,0 +1,6 @@
+drop table pending_logins cascade;
+drop table recovery_codes cascade;
+
+alter table users
+    drop column totp_enabled_at,
+    drop column totp_secret;
@@ -0,0 +1,21 @@
+alter table users
+    add column totp_secret varchar,
+    add column totp_enabled_at timestamp;
+
+create table recovery_codes
+(
+    id uuid not null default gen_random_uuid() primary key,
+    user_id uuid not null,
+    code_hash varchar not null,
+    used_at timestamp,
+    foreign key (user_id) references users(id)
+);
+
+create table pending_logins
+(
+    id uuid not null default gen_random_uuid() primary key,
+    user_id uuid not null,
+    remember boolean not null,
+    created_at timestamp not null default now(),
+    foreign key (user_id) references users(id)
+);
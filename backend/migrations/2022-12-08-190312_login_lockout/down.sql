@@ -0,0 +1,2 @@
+drop table audit_log cascade;
+drop table login_failures cascade;
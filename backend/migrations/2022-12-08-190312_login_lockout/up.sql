@@ -0,0 +1,21 @@
+create table login_failures
+(
+    id uuid not null default gen_random_uuid() primary key,
+    login varchar,
+    ip varchar not null,
+    created_at timestamp not null default now()
+);
+
+create index login_failures_login_idx on login_failures (login, created_at);
+create index login_failures_ip_idx on login_failures (ip, created_at);
+
+create table audit_log
+(
+    id uuid not null default gen_random_uuid() primary key,
+    event varchar not null,
+    user_id uuid,
+    ip varchar,
+    details varchar not null,
+    created_at timestamp not null default now(),
+    foreign key (user_id) references users(id)
+);
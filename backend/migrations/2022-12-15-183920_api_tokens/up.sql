@@ -0,0 +1,12 @@
+create table api_tokens
+(
+    id uuid not null default gen_random_uuid() primary key,
+    user_id uuid not null,
+    name varchar not null,
+    token_hash varchar not null unique,
+    scopes text[] not null,
+    created_at timestamp not null default now(),
+    expires_at timestamp,
+    last_used_at timestamp,
+    foreign key (user_id) references users(id)
+);
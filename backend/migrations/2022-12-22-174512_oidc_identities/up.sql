@@ -0,0 +1,9 @@
+create table oidc_identities
+(
+    issuer varchar not null,
+    subject varchar not null,
+    user_id uuid not null,
+    created_at timestamp not null default now(),
+    primary key (issuer, subject),
+    foreign key (user_id) references users(id)
+);
@@ -0,0 +1 @@
+alter table sessions drop column impersonator_id;
@@ -0,0 +1 @@
+alter table sessions add column impersonator_id uuid references users(id);
@@ -0,0 +1,3 @@
+drop table invitations cascade;
+delete from teachers where user_id is null;
+alter table teachers alter column user_id set not null;
@@ -0,0 +1,20 @@
+alter table teachers alter column user_id drop not null;
+
+create table invitations
+(
+    id uuid not null default gen_random_uuid() primary key,
+    code_hash varchar not null unique,
+    student_id uuid,
+    teacher_id uuid,
+    created_by uuid not null,
+    created_at timestamp not null default now(),
+    expires_at timestamp not null,
+    redeemed_at timestamp,
+    redeemed_by uuid,
+    revoked_at timestamp,
+    foreign key (student_id) references students(id) on delete cascade,
+    foreign key (teacher_id) references teachers(id) on delete cascade,
+    foreign key (created_by) references users(id),
+    foreign key (redeemed_by) references users(id),
+    check ((student_id is null) <> (teacher_id is null))
+);
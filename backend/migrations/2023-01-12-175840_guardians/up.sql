@@ -0,0 +1,15 @@
+create table guardians
+(
+    primary key (user_id, student_id),
+    user_id uuid not null,
+    student_id uuid not null,
+    relationship varchar not null,
+    primary_contact boolean not null default false,
+    emergency_contact boolean not null default false,
+    created_at timestamp not null default now(),
+    foreign key (user_id) references users(id) on delete cascade,
+    foreign key (student_id) references students(id) on delete cascade,
+    check (relationship in ('mother', 'father', 'legal_guardian', 'other'))
+);
+
+create index guardians_student_id on guardians(student_id);
@@ -0,0 +1,3 @@
+alter table users
+    drop column pending_email,
+    drop column deleted_at;
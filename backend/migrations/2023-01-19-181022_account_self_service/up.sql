@@ -0,0 +1,3 @@
+alter table users
+    add column pending_email varchar,
+    add column deleted_at timestamp;
@@ -0,0 +1,7 @@
+delete from grades where value is null;
+alter table grades
+    drop column symbol,
+    drop column counts,
+    alter column value set not null;
+
+drop table grade_symbols;
@@ -0,0 +1,20 @@
+-- schools without rows here use the default Polish scale
+create table grade_symbols
+(
+    primary key (school_id, symbol),
+    school_id uuid not null,
+    symbol varchar not null,
+    value float,
+    counts boolean not null,
+    foreign key (school_id) references schools(id) on delete cascade,
+    check (value is not null or not counts)
+);
+
+alter table grades
+    add column symbol varchar,
+    add column counts boolean not null default true;
+update grades set symbol = value::text;
+alter table grades
+    alter column symbol set not null,
+    alter column value drop not null,
+    add check (value is not null or not counts);
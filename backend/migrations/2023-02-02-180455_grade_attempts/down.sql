@@ -0,0 +1,8 @@
+alter table schools drop column retake_policy;
+
+delete from grades where attempt > 1;
+alter table grades
+    drop column corrects_id,
+    drop column attempt,
+    drop column id,
+    add primary key (student_id, subject_id, task_id);
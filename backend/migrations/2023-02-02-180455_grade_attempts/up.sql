@@ -0,0 +1,13 @@
+alter table grades
+    drop constraint grades_pkey,
+    add column id uuid not null default gen_random_uuid() primary key,
+    add column attempt int not null default 1,
+    add column corrects_id uuid,
+    add foreign key (corrects_id) references grades(id),
+    add unique (student_id, subject_id, task_id, attempt),
+    add check (attempt > 1 or corrects_id is null);
+
+-- which attempt of a task counts towards averages: latest, best or average
+alter table schools
+    add column retake_policy varchar not null default 'latest',
+    add check (retake_policy in ('latest', 'best', 'average'));
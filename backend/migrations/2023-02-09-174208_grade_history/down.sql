@@ -0,0 +1,4 @@
+drop trigger grades_history on grades;
+drop function record_grade_change();
+drop table grade_history;
+drop function forbid_grade_history_changes();
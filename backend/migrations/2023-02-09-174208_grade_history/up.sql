@@ -0,0 +1,73 @@
+-- old_* columns are null for new grades, new_* columns for deleted ones
+create table grade_history
+(
+    id uuid not null default gen_random_uuid() primary key,
+    grade_id uuid not null,
+    student_id uuid not null,
+    old_symbol varchar,
+    new_symbol varchar,
+    old_value double precision,
+    new_value double precision,
+    old_weight int,
+    new_weight int,
+    teacher_id uuid not null,
+    changed_by uuid,
+    reason varchar,
+    changed_at timestamp not null default now(),
+    foreign key (changed_by) references users(id)
+);
+
+create index grade_history_grade_id_idx on grade_history (grade_id);
+
+-- the application names the acting user and the reason with set_config in the
+-- transaction of the change, both are null for changes made outside of it
+create function record_grade_change() returns trigger as $$
+declare
+    changed_by uuid := nullif(current_setting('bibrus.changed_by', true), '')::uuid;
+    reason varchar := nullif(current_setting('bibrus.change_reason', true), '');
+begin
+    if tg_op = 'INSERT' then
+        insert into grade_history (grade_id, student_id, new_symbol, new_value, new_weight,
+                                   teacher_id, changed_by, reason)
+        values (new.id, new.student_id, new.symbol, new.value, new.weight,
+                new.teacher_id, changed_by, reason);
+        return new;
+    elsif tg_op = 'UPDATE' then
+        insert into grade_history (grade_id, student_id, old_symbol, new_symbol, old_value,
+                                   new_value, old_weight, new_weight, teacher_id, changed_by,
+                                   reason)
+        values (new.id, new.student_id, old.symbol, new.symbol, old.value,
+                new.value, old.weight, new.weight, new.teacher_id, changed_by,
+                reason);
+        return new;
+    else
+        insert into grade_history (grade_id, student_id, old_symbol, old_value, old_weight,
+                                   teacher_id, changed_by, reason)
+        values (old.id, old.student_id, old.symbol, old.value, old.weight,
+                old.teacher_id, changed_by, reason);
+        return old;
+    end if;
+end;
+$$ language plpgsql;
+
+create trigger grades_history
+    after insert or update or delete on grades
+    for each row execute function record_grade_change();
+
+create function forbid_grade_history_changes() returns trigger as $$
+begin
+    raise exception 'grade history is append-only';
+end;
+$$ language plpgsql;
+
+create trigger grade_history_append_only
+    before update or delete on grade_history
+    for each row execute function forbid_grade_history_changes();
+
+create trigger grade_history_no_truncate
+    before truncate on grade_history
+    for each statement execute function forbid_grade_history_changes();
+
+-- grades given before the history existed
+insert into grade_history (grade_id, student_id, new_symbol, new_value, new_weight, teacher_id)
+select id, student_id, symbol, value, weight, teacher_id from grades;
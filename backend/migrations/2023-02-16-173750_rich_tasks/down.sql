@@ -0,0 +1,7 @@
+alter table tasks
+    drop column class_id,
+    drop column category,
+    drop column weight,
+    drop column date,
+    drop column description,
+    drop column max_points;
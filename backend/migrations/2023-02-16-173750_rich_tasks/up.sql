@@ -0,0 +1,16 @@
+-- tasks from before they belonged to classes keep class_id and date null and become
+-- tests of weight 1
+alter table tasks
+    add column class_id uuid references classes(id),
+    add column category varchar not null default 'test',
+    add column weight int not null default 1,
+    add column date date,
+    add column description varchar,
+    add column max_points double precision,
+    add check (category in ('test', 'quiz', 'homework', 'activity')),
+    add check (weight > 0),
+    add check (max_points > 0);
+
+alter table tasks
+    alter column category drop default,
+    alter column weight drop default;
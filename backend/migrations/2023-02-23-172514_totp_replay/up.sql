@@ -0,0 +1,2 @@
+-- time step of the last accepted code, codes from it or earlier steps are refused
+alter table users add column totp_last_step bigint;
@@ -0,0 +1 @@
+alter table pending_logins drop column invitation;
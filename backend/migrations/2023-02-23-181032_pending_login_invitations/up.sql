@@ -0,0 +1,2 @@
+-- invitation code sent with the password, redeemed once the second factor passes
+alter table pending_logins add column invitation varchar;
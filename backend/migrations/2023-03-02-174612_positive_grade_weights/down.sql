@@ -0,0 +1 @@
+alter table grades drop constraint grades_weight_check;
@@ -0,0 +1,6 @@
+-- grades which got a weight below 1 before it was checked count like tests of weight 1,
+-- the history keeps the old weight
+select set_config('bibrus.change_reason', 'Weights below 1 are no longer allowed', true);
+update grades set weight = 1 where weight < 1;
+
+alter table grades add constraint grades_weight_check check (weight > 0);
@@ -0,0 +1 @@
+alter table pending_logins rename column invitation_hash to invitation;
@@ -0,0 +1,3 @@
+-- invitation codes are only stored hashed, logins waiting with a plain code start over
+delete from pending_logins where invitation is not null;
+alter table pending_logins rename column invitation to invitation_hash;
@@ -0,0 +1,2 @@
+alter table users add constraint users_role_check
+    check (role in ('system_admin', 'school_admin', 'teacher', 'student', 'parent'));
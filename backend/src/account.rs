@@ -0,0 +1,156 @@
+use crate::{
+    audit,
+    auth::{
+        consume_token, get_by_email, get_by_login, hash_pass, issue_token, random_token, AuthError,
+        PgConn, EMAIL_CHANGE,
+    },
+    config::PasswordConfig,
+    models::{NewAuditEntry, User},
+    schema::{
+        api_tokens, guardians, oidc_identities, pending_logins, recovery_codes, sessions,
+        user_tokens, users,
+    },
+};
+use anyhow::Context;
+use diesel::{
+    delete,
+    prelude::*,
+    result::{DatabaseErrorKind, Error as DieselError},
+    update,
+};
+use std::{net::IpAddr, time::SystemTime};
+
+/// Changes to the own account need the current password, a stolen session isn't enough
+pub fn check_password(user: &User, pass: &str) -> Result<(), AuthError> {
+    if !argon2::verify_encoded(&user.password, pass.as_bytes())
+        .context("Failed to verify password")?
+    {
+        println!("Wrong password of user {}", user.login);
+        return Err(AuthError::IncorrectPassword);
+    }
+    Ok(())
+}
+
+pub fn change_login(conn: &mut PgConn, user: &User, new_login: &str) -> Result<User, AuthError> {
+    if new_login == user.login {
+        return Ok(user.clone());
+    }
+    if get_by_login(conn, new_login)?.is_some() {
+        println!("User with this name already exists");
+        return Err(AuthError::UserAlreadyExists);
+    }
+
+    let user = update(users::table.find(user.id))
+        .set(users::login.eq(new_login))
+        .get_result::<User>(conn)
+        .map_err(unique_violation)?;
+    println!("User {} changed their login", user.id);
+    Ok(user)
+}
+
+/// Keeps the new address aside until it's confirmed, returns a token to mail to it
+pub fn request_email_change(
+    conn: &mut PgConn,
+    user: &User,
+    new_email: &str,
+    lifetime: time::Duration,
+) -> Result<String, AuthError> {
+    if get_by_email(conn, new_email)?.is_some() {
+        println!("User with this email already exists");
+        return Err(AuthError::UserAlreadyExists);
+    }
+
+    update(users::table.find(user.id))
+        .set(users::pending_email.eq(new_email))
+        .execute(conn)
+        .context("Failed to store pending email")?;
+    Ok(issue_token(conn, user.id, EMAIL_CHANGE, lifetime)?)
+}
+
+/// Switches to the pending address using a token mailed to it
+pub fn confirm_email_change(conn: &mut PgConn, token: &str) -> Result<User, AuthError> {
+    conn.transaction(|conn| {
+        let token = consume_token(conn, EMAIL_CHANGE, token)?;
+        let user = users::table
+            .find(token.user_id)
+            .first::<User>(conn)
+            .context("Failed to fetch user")?;
+        let new_email = user.pending_email.ok_or(AuthError::InvalidToken)?;
+
+        // someone may have registered the address in the meantime
+        let user = update(users::table.find(user.id))
+            .set((
+                users::email.eq(new_email),
+                users::pending_email.eq(None::<String>),
+                users::verified_at.eq(SystemTime::now()),
+            ))
+            .get_result::<User>(conn)
+            .map_err(unique_violation)?;
+        println!("User {} confirmed a new email", user.login);
+        Ok(user)
+    })
+}
+
+/// Anonymises the account and removes everything which lets anyone log in as it.
+/// The row itself stays, students and teachers keep pointing at it, so that school
+/// records remain complete.
+pub fn delete_account(
+    conn: &mut PgConn,
+    user: &User,
+    ip: IpAddr,
+    config: &PasswordConfig,
+) -> Result<(), AuthError> {
+    // nobody knows this password, it is hashed outside of the transaction as hashing is slow
+    let unusable = hash_pass(&random_token(), config)?;
+    conn.transaction(|conn| {
+        let placeholder = format!("deleted_{}", user.id.simple());
+        update(users::table.find(user.id))
+            .set((
+                users::login.eq(&placeholder),
+                users::email.eq(format!("{placeholder}@deleted.invalid")),
+                users::password.eq(&unusable),
+                users::verified_at.eq(None::<SystemTime>),
+                users::totp_secret.eq(None::<String>),
+                users::totp_enabled_at.eq(None::<SystemTime>),
+                users::pending_email.eq(None::<String>),
+                users::deleted_at.eq(SystemTime::now()),
+            ))
+            .execute(conn)?;
+
+        delete(
+            sessions::table.filter(
+                sessions::user_id
+                    .eq(user.id)
+                    .or(sessions::impersonator_id.eq(user.id)),
+            ),
+        )
+        .execute(conn)?;
+        delete(api_tokens::table.filter(api_tokens::user_id.eq(user.id))).execute(conn)?;
+        delete(user_tokens::table.filter(user_tokens::user_id.eq(user.id))).execute(conn)?;
+        delete(pending_logins::table.filter(pending_logins::user_id.eq(user.id))).execute(conn)?;
+        delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user.id))).execute(conn)?;
+        delete(oidc_identities::table.filter(oidc_identities::user_id.eq(user.id)))
+            .execute(conn)?;
+        delete(guardians::table.filter(guardians::user_id.eq(user.id))).execute(conn)?;
+
+        audit::record(
+            conn,
+            NewAuditEntry {
+                event: audit::ACCOUNT_DELETED,
+                user_id: Some(user.id),
+                ip: Some(ip.to_string()),
+                details: format!("Account {} deleted by its owner", user.id),
+            },
+        )?;
+        Ok(())
+    })
+}
+
+fn unique_violation(e: DieselError) -> AuthError {
+    match e {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            AuthError::UserAlreadyExists
+        }
+        e => e.into(),
+    }
+}
@@ -6,14 +6,20 @@ use crate::schema::{
     class_students, classes, grades, groups, schools, students, subjects, tasks, teachers,
 };
 use crate::{
-    models::{Class, ClassStudent, Grade, Group, School, Student, Subject, Task, Teacher},
+    grading,
+    models::{
+        Class, ClassStudent, Grade, Group, NewGrade, NewTask, School, Student, Subject, Task,
+        Teacher, UpdateClass, UpdateGrade, UpdateGroup, UpdateSchool, UpdateStudent, UpdateSubject,
+        UpdateTask, UpdateTeacher,
+    },
     schema,
 };
-use anyhow::{self, Context};
-use diesel::insert_into;
+use anyhow;
+use diesel::{delete, insert_into, update};
 use diesel::{
     prelude::*,
     r2d2::{ConnectionManager, PooledConnection},
+    result::{DatabaseErrorKind, EmptyChangeset},
 };
 use thiserror::Error;
 use time::Date;
@@ -23,16 +29,55 @@ pub type PgConn = PooledConnection<ConnectionManager<PgConnection>>;
 
 #[derive(Error, Debug)]
 pub enum Error {
+    #[error("Record not found")]
+    NotFound,
+    #[error("Record already exists: {0}")]
+    AlreadyExists(String),
+    #[error("Record references missing or is referenced by existing data: {0}")]
+    ForeignKeyViolation(String),
+    #[error("Nothing to update")]
+    NothingToUpdate,
+    #[error("Grade {0} is not on the school's grading scale")]
+    NotOnScale(String),
+    #[error("Invalid grading scale: {0}")]
+    InvalidScale(String),
+    #[error("Invalid correction: {0}")]
+    InvalidCorrection(String),
+    #[error("Changing or deleting a grade requires a reason")]
+    ReasonRequired,
+    #[error("Grade needs a subject, its task doesn't belong to a class")]
+    SubjectRequired,
+    #[error("Grade doesn't match its task's class: {0}")]
+    NotInClass(String),
+    #[error("Weight has to be at least 1")]
+    InvalidWeight,
     #[error(transparent)]
     Unexpected(#[from] anyhow::Error),
 }
 
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Self {
+        use diesel::result::Error::*;
+        match e {
+            NotFound => Error::NotFound,
+            DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) => {
+                Error::ForeignKeyViolation(info.message().to_string())
+            }
+            DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                Error::AlreadyExists(info.message().to_string())
+            }
+            QueryBuilderError(e) if e.is::<EmptyChangeset>() => Error::NothingToUpdate,
+            e => Error::Unexpected(anyhow::Error::new(e).context("Database query failed")),
+        }
+    }
+}
+
 pub fn create_school(
     conn: &mut PgConn,
     school_name: &str,
     school_place: &str,
     s_type: Option<&str>,
-) -> anyhow::Result<School> {
+) -> Result<School, Error> {
     insert_into(schools)
         .values((
             schools::name.eq(school_name),
@@ -40,7 +85,7 @@ pub fn create_school(
             schools::school_type.eq(s_type),
         ))
         .get_result::<School>(conn)
-        .context("Failed to create school")
+        .map_err(Error::from)
 }
 
 pub fn create_student(
@@ -51,7 +96,7 @@ pub fn create_student(
     student_school_id: Uuid,
     student_group_id: Uuid,
     student_user_id: Option<Uuid>,
-) -> anyhow::Result<Student> {
+) -> Result<Student, Error> {
     insert_into(students)
         .values((
             students::first_name.eq(student_first_name),
@@ -62,16 +107,16 @@ pub fn create_student(
             students::user_id.eq(student_user_id),
         ))
         .get_result::<Student>(conn)
-        .context("Failed to create student")
+        .map_err(Error::from)
 }
 
 pub fn create_teacher(
     conn: &mut PgConn,
     teacher_first_name: &str,
     teacher_last_name: &str,
-    teacher_user_id: Uuid,
+    teacher_user_id: Option<Uuid>,
     teacher_school_id: Uuid,
-) -> anyhow::Result<Teacher> {
+) -> Result<Teacher, Error> {
     insert_into(teachers)
         .values((
             teachers::first_name.eq(teacher_first_name),
@@ -80,35 +125,35 @@ pub fn create_teacher(
             teachers::user_id.eq(teacher_user_id),
         ))
         .get_result::<Teacher>(conn)
-        .context("Failed to create teacher")
+        .map_err(Error::from)
 }
 
 pub fn create_subject(
     conn: &mut PgConn,
     subject_name: &str,
     school_uuid: Uuid,
-) -> anyhow::Result<Subject> {
+) -> Result<Subject, Error> {
     insert_into(subjects)
         .values((
             subjects::name.eq(subject_name),
             subjects::school_id.eq(school_uuid),
         ))
         .get_result::<Subject>(conn)
-        .context("Failed to create subject")
+        .map_err(Error::from)
 }
 
 pub fn create_group(
     conn: &mut PgConn,
     group_name: &str,
     school_uuid: Uuid,
-) -> anyhow::Result<Group> {
+) -> Result<Group, Error> {
     insert_into(groups)
         .values((
             groups::name.eq(group_name),
             groups::school_id.eq(school_uuid),
         ))
         .get_result::<Group>(conn)
-        .context("Failed to create group")
+        .map_err(Error::from)
 }
 
 pub fn create_class(
@@ -116,7 +161,7 @@ pub fn create_class(
     class_subject_id: Uuid,
     class_group_id: Uuid,
     class_teacher_id: Uuid,
-) -> anyhow::Result<Class> {
+) -> Result<Class, Error> {
     insert_into(classes)
         .values((
             classes::subject_id.eq(class_subject_id),
@@ -124,48 +169,524 @@ pub fn create_class(
             classes::teacher_id.eq(class_teacher_id),
         ))
         .get_result::<Class>(conn)
-        .context("Failed to create class")
+        .map_err(Error::from)
 }
 
 pub fn add_student_to_class(
     conn: &mut PgConn,
     student_uuid: Uuid,
     class_uuid: Uuid,
-) -> anyhow::Result<ClassStudent> {
+) -> Result<ClassStudent, Error> {
     insert_into(class_students)
         .values((
             class_students::student_id.eq(student_uuid),
             class_students::class_id.eq(class_uuid),
         ))
         .get_result::<ClassStudent>(conn)
-        .context("Failed to add student to class")
+        .map_err(Error::from)
 }
 
+/// Records a grade, its symbol has to be on the scale of the student's school.
+/// A retake or correction points at an earlier attempt at the same task with `corrects`
+/// and becomes the next attempt, otherwise the grade is the first attempt.
+/// Without a weight of its own the grade takes it from its task. A task of a class gives the
+/// grade its subject and only takes grades of the class's students.
+#[allow(clippy::too_many_arguments)]
 pub fn create_grade(
     conn: &mut PgConn,
-    grade_value: f64,
-    grade_weight: i32,
+    grade_symbol: &str,
+    grade_weight: Option<i32>,
     grade_teacher_id: Uuid,
     grade_student_id: Uuid,
-    grade_subject_id: Uuid,
+    grade_subject_id: Option<Uuid>,
     grade_task_id: Uuid,
-) -> anyhow::Result<Grade> {
-    insert_into(grades)
-        .values((
-            grades::value.eq(grade_value),
-            grades::weight.eq(grade_weight),
-            grades::teacher_id.eq(grade_teacher_id),
-            grades::student_id.eq(grade_student_id),
-            grades::subject_id.eq(grade_subject_id),
-            grades::task_id.eq(grade_task_id),
-        ))
-        .get_result::<Grade>(conn)
-        .context("Failed to create grade")
+    corrects: Option<Uuid>,
+    given_by: Option<Uuid>,
+) -> Result<Grade, Error> {
+    let school = school_of_student(conn, grade_student_id)?;
+    let entry = grading::find_symbol(conn, school, grade_symbol)?;
+    let task = get_task(conn, grade_task_id)?;
+    let grade_weight = grade_weight.unwrap_or(task.weight);
+    if grade_weight < 1 {
+        return Err(Error::InvalidWeight);
+    }
+    let grade_subject_id = match task.class_id {
+        Some(class) => {
+            let class_subject = get_class(conn, class)?.subject_id;
+            if grade_subject_id.is_some_and(|subject| subject != class_subject) {
+                return Err(Error::NotInClass(
+                    "Subject differs from the class's subject".to_string(),
+                ));
+            }
+            match get_class_student(conn, class, grade_student_id) {
+                Err(Error::NotFound) => {
+                    return Err(Error::NotInClass(
+                        "Student doesn't attend the class".to_string(),
+                    ))
+                }
+                result => result?,
+            };
+            class_subject
+        }
+        None => grade_subject_id.ok_or(Error::SubjectRequired)?,
+    };
+
+    conn.transaction(|conn| {
+        if let Some(user_uuid) = given_by {
+            grading::set_change_author(conn, user_uuid, None)?;
+        }
+        let grade_attempt = match corrects {
+            Some(corrected) => {
+                let original = grades
+                    .find(corrected)
+                    .first::<Grade>(conn)
+                    .optional()?
+                    .ok_or_else(|| {
+                        Error::InvalidCorrection("Corrected grade doesn't exist".to_string())
+                    })?;
+                if (original.student_id, original.subject_id, original.task_id)
+                    != (grade_student_id, grade_subject_id, grade_task_id)
+                {
+                    return Err(Error::InvalidCorrection(
+                        "Corrected grade is for another student, subject or task".to_string(),
+                    ));
+                }
+                let latest = grades
+                    .filter(grades::student_id.eq(grade_student_id))
+                    .filter(grades::subject_id.eq(grade_subject_id))
+                    .filter(grades::task_id.eq(grade_task_id))
+                    .select(diesel::dsl::max(grades::attempt))
+                    .first::<Option<i32>>(conn)?;
+                latest.unwrap_or_default() + 1
+            }
+            None => 1,
+        };
+
+        insert_into(grades)
+            .values(&NewGrade {
+                value: entry.value,
+                weight: grade_weight,
+                task_id: grade_task_id,
+                student_id: grade_student_id,
+                subject_id: grade_subject_id,
+                teacher_id: grade_teacher_id,
+                symbol: &entry.symbol,
+                counts: entry.counts,
+                attempt: grade_attempt,
+                corrects_id: corrects,
+            })
+            .get_result::<Grade>(conn)
+            .map_err(Error::from)
+    })
 }
 
-pub fn create_task(conn: &mut PgConn, task_name: &str) -> anyhow::Result<Task> {
+pub fn create_task(conn: &mut PgConn, task: &NewTask) -> Result<Task, Error> {
     insert_into(tasks)
-        .values(tasks::name.eq(task_name))
+        .values(task)
         .get_result::<Task>(conn)
-        .context("Failed to create task")
+        .map_err(Error::from)
+}
+
+pub fn get_school(conn: &mut PgConn, school_uuid: Uuid) -> Result<School, Error> {
+    schools
+        .find(school_uuid)
+        .first::<School>(conn)
+        .map_err(Error::from)
+}
+
+pub fn list_schools(conn: &mut PgConn, school: Option<Uuid>) -> Result<Vec<School>, Error> {
+    let mut query = schools.into_boxed();
+    if let Some(school_uuid) = school {
+        query = query.filter(schools::id.eq(school_uuid));
+    }
+    query.load::<School>(conn).map_err(Error::from)
+}
+
+pub fn update_school(
+    conn: &mut PgConn,
+    school_uuid: Uuid,
+    changes: &UpdateSchool,
+) -> Result<School, Error> {
+    update(schools.find(school_uuid))
+        .set(changes)
+        .get_result::<School>(conn)
+        .map_err(Error::from)
+}
+
+pub fn delete_school(conn: &mut PgConn, school_uuid: Uuid) -> Result<School, Error> {
+    delete(schools.find(school_uuid))
+        .get_result::<School>(conn)
+        .map_err(Error::from)
+}
+
+pub fn get_student(conn: &mut PgConn, student_uuid: Uuid) -> Result<Student, Error> {
+    students
+        .find(student_uuid)
+        .first::<Student>(conn)
+        .map_err(Error::from)
+}
+
+pub fn list_students(conn: &mut PgConn, school: Option<Uuid>) -> Result<Vec<Student>, Error> {
+    let mut query = students.into_boxed();
+    if let Some(school_uuid) = school {
+        query = query.filter(students::school_id.eq(school_uuid));
+    }
+    query.load::<Student>(conn).map_err(Error::from)
+}
+
+pub fn update_student(
+    conn: &mut PgConn,
+    student_uuid: Uuid,
+    changes: &UpdateStudent,
+) -> Result<Student, Error> {
+    update(students.find(student_uuid))
+        .set(changes)
+        .get_result::<Student>(conn)
+        .map_err(Error::from)
+}
+
+pub fn delete_student(conn: &mut PgConn, student_uuid: Uuid) -> Result<Student, Error> {
+    delete(students.find(student_uuid))
+        .get_result::<Student>(conn)
+        .map_err(Error::from)
+}
+
+pub fn get_teacher(conn: &mut PgConn, teacher_uuid: Uuid) -> Result<Teacher, Error> {
+    teachers
+        .find(teacher_uuid)
+        .first::<Teacher>(conn)
+        .map_err(Error::from)
+}
+
+pub fn list_teachers(conn: &mut PgConn, school: Option<Uuid>) -> Result<Vec<Teacher>, Error> {
+    let mut query = teachers.into_boxed();
+    if let Some(school_uuid) = school {
+        query = query.filter(teachers::school_id.eq(school_uuid));
+    }
+    query.load::<Teacher>(conn).map_err(Error::from)
+}
+
+pub fn update_teacher(
+    conn: &mut PgConn,
+    teacher_uuid: Uuid,
+    changes: &UpdateTeacher,
+) -> Result<Teacher, Error> {
+    update(teachers.find(teacher_uuid))
+        .set(changes)
+        .get_result::<Teacher>(conn)
+        .map_err(Error::from)
+}
+
+pub fn delete_teacher(conn: &mut PgConn, teacher_uuid: Uuid) -> Result<Teacher, Error> {
+    delete(teachers.find(teacher_uuid))
+        .get_result::<Teacher>(conn)
+        .map_err(Error::from)
+}
+
+pub fn get_subject(conn: &mut PgConn, subject_uuid: Uuid) -> Result<Subject, Error> {
+    subjects
+        .find(subject_uuid)
+        .first::<Subject>(conn)
+        .map_err(Error::from)
+}
+
+pub fn list_subjects(conn: &mut PgConn, school: Option<Uuid>) -> Result<Vec<Subject>, Error> {
+    let mut query = subjects.into_boxed();
+    if let Some(school_uuid) = school {
+        query = query.filter(subjects::school_id.eq(school_uuid));
+    }
+    query.load::<Subject>(conn).map_err(Error::from)
+}
+
+pub fn update_subject(
+    conn: &mut PgConn,
+    subject_uuid: Uuid,
+    changes: &UpdateSubject,
+) -> Result<Subject, Error> {
+    update(subjects.find(subject_uuid))
+        .set(changes)
+        .get_result::<Subject>(conn)
+        .map_err(Error::from)
+}
+
+pub fn delete_subject(conn: &mut PgConn, subject_uuid: Uuid) -> Result<Subject, Error> {
+    delete(subjects.find(subject_uuid))
+        .get_result::<Subject>(conn)
+        .map_err(Error::from)
+}
+
+pub fn get_group(conn: &mut PgConn, group_uuid: Uuid) -> Result<Group, Error> {
+    groups
+        .find(group_uuid)
+        .first::<Group>(conn)
+        .map_err(Error::from)
+}
+
+pub fn list_groups(conn: &mut PgConn, school: Option<Uuid>) -> Result<Vec<Group>, Error> {
+    let mut query = groups.into_boxed();
+    if let Some(school_uuid) = school {
+        query = query.filter(groups::school_id.eq(school_uuid));
+    }
+    query.load::<Group>(conn).map_err(Error::from)
+}
+
+pub fn update_group(
+    conn: &mut PgConn,
+    group_uuid: Uuid,
+    changes: &UpdateGroup,
+) -> Result<Group, Error> {
+    update(groups.find(group_uuid))
+        .set(changes)
+        .get_result::<Group>(conn)
+        .map_err(Error::from)
+}
+
+pub fn delete_group(conn: &mut PgConn, group_uuid: Uuid) -> Result<Group, Error> {
+    delete(groups.find(group_uuid))
+        .get_result::<Group>(conn)
+        .map_err(Error::from)
+}
+
+pub fn get_class(conn: &mut PgConn, class_uuid: Uuid) -> Result<Class, Error> {
+    classes
+        .find(class_uuid)
+        .first::<Class>(conn)
+        .map_err(Error::from)
+}
+
+pub fn list_classes(conn: &mut PgConn, school: Option<Uuid>) -> Result<Vec<Class>, Error> {
+    let mut query = classes
+        .inner_join(groups)
+        .select(classes::all_columns)
+        .into_boxed();
+    if let Some(school_uuid) = school {
+        query = query.filter(groups::school_id.eq(school_uuid));
+    }
+    query.load::<Class>(conn).map_err(Error::from)
+}
+
+pub fn update_class(
+    conn: &mut PgConn,
+    class_uuid: Uuid,
+    changes: &UpdateClass,
+) -> Result<Class, Error> {
+    update(classes.find(class_uuid))
+        .set(changes)
+        .get_result::<Class>(conn)
+        .map_err(Error::from)
+}
+
+pub fn delete_class(conn: &mut PgConn, class_uuid: Uuid) -> Result<Class, Error> {
+    delete(classes.find(class_uuid))
+        .get_result::<Class>(conn)
+        .map_err(Error::from)
+}
+
+pub fn get_task(conn: &mut PgConn, task_uuid: Uuid) -> Result<Task, Error> {
+    tasks
+        .find(task_uuid)
+        .first::<Task>(conn)
+        .map_err(Error::from)
+}
+
+/// Tasks of the school's classes, newest first. Tasks from before tasks belonged to classes
+/// are only listed without a school filter.
+pub fn list_tasks(
+    conn: &mut PgConn,
+    school: Option<Uuid>,
+    class: Option<Uuid>,
+) -> Result<Vec<Task>, Error> {
+    let mut query = tasks
+        .left_join(classes.inner_join(groups))
+        .select(tasks::all_columns)
+        .order((tasks::date.desc().nulls_last(), tasks::name))
+        .into_boxed();
+    if let Some(school_uuid) = school {
+        query = query.filter(groups::school_id.nullable().eq(school_uuid));
+    }
+    if let Some(class_uuid) = class {
+        query = query.filter(tasks::class_id.eq(class_uuid));
+    }
+    query.load::<Task>(conn).map_err(Error::from)
+}
+
+pub fn update_task(
+    conn: &mut PgConn,
+    task_uuid: Uuid,
+    changes: &UpdateTask,
+) -> Result<Task, Error> {
+    update(tasks.find(task_uuid))
+        .set(changes)
+        .get_result::<Task>(conn)
+        .map_err(Error::from)
+}
+
+pub fn delete_task(conn: &mut PgConn, task_uuid: Uuid) -> Result<Task, Error> {
+    delete(tasks.find(task_uuid))
+        .get_result::<Task>(conn)
+        .map_err(Error::from)
+}
+
+pub fn get_class_student(
+    conn: &mut PgConn,
+    class_uuid: Uuid,
+    student_uuid: Uuid,
+) -> Result<ClassStudent, Error> {
+    class_students
+        .find((class_uuid, student_uuid))
+        .first::<ClassStudent>(conn)
+        .map_err(Error::from)
+}
+
+pub fn list_class_students(
+    conn: &mut PgConn,
+    school: Option<Uuid>,
+    class: Option<Uuid>,
+) -> Result<Vec<ClassStudent>, Error> {
+    let mut query = class_students
+        .inner_join(students)
+        .select(class_students::all_columns)
+        .into_boxed();
+    if let Some(school_uuid) = school {
+        query = query.filter(students::school_id.eq(school_uuid));
+    }
+    if let Some(class_uuid) = class {
+        query = query.filter(class_students::class_id.eq(class_uuid));
+    }
+    query.load::<ClassStudent>(conn).map_err(Error::from)
+}
+
+pub fn remove_student_from_class(
+    conn: &mut PgConn,
+    class_uuid: Uuid,
+    student_uuid: Uuid,
+) -> Result<ClassStudent, Error> {
+    delete(class_students.find((class_uuid, student_uuid)))
+        .get_result::<ClassStudent>(conn)
+        .map_err(Error::from)
+}
+
+pub fn get_grade(conn: &mut PgConn, grade_uuid: Uuid) -> Result<Grade, Error> {
+    grades
+        .find(grade_uuid)
+        .first::<Grade>(conn)
+        .map_err(Error::from)
+}
+
+pub fn list_grades(
+    conn: &mut PgConn,
+    school: Option<Uuid>,
+    student: Option<Uuid>,
+) -> Result<Vec<Grade>, Error> {
+    let mut query = grades
+        .inner_join(students)
+        .select(grades::all_columns)
+        .into_boxed();
+    if let Some(school_uuid) = school {
+        query = query.filter(students::school_id.eq(school_uuid));
+    }
+    if let Some(student_uuid) = student {
+        query = query.filter(grades::student_id.eq(student_uuid));
+    }
+    query.load::<Grade>(conn).map_err(Error::from)
+}
+
+/// Changes are kept in the grade's history together with the user and their reason
+pub fn update_grade(
+    conn: &mut PgConn,
+    grade_uuid: Uuid,
+    changes: &UpdateGrade,
+    changed_by: Uuid,
+    reason: &str,
+) -> Result<Grade, Error> {
+    if reason.trim().is_empty() {
+        return Err(Error::ReasonRequired);
+    }
+    if changes.weight.is_some_and(|new_weight| new_weight < 1) {
+        return Err(Error::InvalidWeight);
+    }
+    let mut changes = changes.clone();
+    if let Some(new_symbol) = &changes.symbol {
+        let grade = get_grade(conn, grade_uuid)?;
+        let school = school_of_student(conn, grade.student_id)?;
+        let entry = grading::find_symbol(conn, school, new_symbol)?;
+        changes.value = Some(entry.value);
+        changes.counts = Some(entry.counts);
+    }
+
+    conn.transaction(|conn| {
+        grading::set_change_author(conn, changed_by, Some(reason))?;
+        update(grades.find(grade_uuid))
+            .set(&changes)
+            .get_result::<Grade>(conn)
+            .map_err(Error::from)
+    })
+}
+
+/// Deletes a grade, ones which were corrected later can't be deleted. The deletion is
+/// kept in the grade's history.
+pub fn delete_grade(
+    conn: &mut PgConn,
+    grade_uuid: Uuid,
+    changed_by: Uuid,
+    reason: &str,
+) -> Result<Grade, Error> {
+    if reason.trim().is_empty() {
+        return Err(Error::ReasonRequired);
+    }
+    conn.transaction(|conn| {
+        grading::set_change_author(conn, changed_by, Some(reason))?;
+        delete(grades.find(grade_uuid))
+            .get_result::<Grade>(conn)
+            .map_err(Error::from)
+    })
+}
+
+pub fn school_of_student(conn: &mut PgConn, student_uuid: Uuid) -> Result<Uuid, Error> {
+    students
+        .filter(students::id.eq(student_uuid))
+        .select(students::school_id)
+        .first::<Uuid>(conn)
+        .map_err(Error::from)
+}
+
+pub fn school_of_teacher(conn: &mut PgConn, teacher_uuid: Uuid) -> Result<Uuid, Error> {
+    teachers
+        .filter(teachers::id.eq(teacher_uuid))
+        .select(teachers::school_id)
+        .first::<Uuid>(conn)
+        .map_err(Error::from)
+}
+
+pub fn school_of_subject(conn: &mut PgConn, subject_uuid: Uuid) -> Result<Uuid, Error> {
+    subjects
+        .filter(subjects::id.eq(subject_uuid))
+        .select(subjects::school_id)
+        .first::<Uuid>(conn)
+        .map_err(Error::from)
+}
+
+pub fn school_of_group(conn: &mut PgConn, group_uuid: Uuid) -> Result<Uuid, Error> {
+    groups
+        .filter(groups::id.eq(group_uuid))
+        .select(groups::school_id)
+        .first::<Uuid>(conn)
+        .map_err(Error::from)
+}
+
+/// `None` for tasks from before tasks belonged to classes
+pub fn school_of_task(conn: &mut PgConn, task_uuid: Uuid) -> Result<Option<Uuid>, Error> {
+    match get_task(conn, task_uuid)?.class_id {
+        Some(class_uuid) => school_of_class(conn, class_uuid).map(Some),
+        None => Ok(None),
+    }
+}
+
+pub fn school_of_class(conn: &mut PgConn, class_uuid: Uuid) -> Result<Uuid, Error> {
+    classes
+        .inner_join(groups)
+        .filter(classes::id.eq(class_uuid))
+        .select(groups::school_id)
+        .first::<Uuid>(conn)
+        .map_err(Error::from)
 }
@@ -0,0 +1,106 @@
+use crate::{
+    auth::{hash_token, random_token, AuthError, PgConn},
+    models::{ApiToken, NewApiToken, Scope, User},
+    schema::{api_tokens, users},
+};
+use anyhow::Context;
+use diesel::{delete, insert_into, prelude::*, update};
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// Makes tokens easy to recognize, e.g. by secret scanners
+const PREFIX: &str = "bib_";
+/// Longest lifetime of a token which expires, about ten years
+pub const MAX_DAYS: i64 = 3650;
+
+/// Creates a token, returns it together with the plain token, which is never stored
+pub fn create_token(
+    conn: &mut PgConn,
+    user_uuid: Uuid,
+    token_name: &str,
+    token_scopes: &[Scope],
+    expires: Option<SystemTime>,
+) -> anyhow::Result<(ApiToken, String)> {
+    let token = format!("{PREFIX}{}", random_token());
+
+    let api_token = insert_into(api_tokens::table)
+        .values(&NewApiToken {
+            user_id: user_uuid,
+            name: token_name,
+            token_hash: &hash_token(&token),
+            scopes: token_scopes,
+            expires_at: expires,
+        })
+        .get_result::<ApiToken>(conn)
+        .context("Failed to insert API token")?;
+
+    println!("Created API token {} for user {}", api_token.id, user_uuid);
+    Ok((api_token, token))
+}
+
+pub fn list_tokens(conn: &mut PgConn, user_uuid: Uuid) -> anyhow::Result<Vec<ApiToken>> {
+    api_tokens::table
+        .filter(api_tokens::user_id.eq(user_uuid))
+        .order(api_tokens::created_at.desc())
+        .load::<ApiToken>(conn)
+        .context("Failed to fetch API tokens")
+}
+
+/// Deletes one of the user's tokens, returns `false` if the user has no such token
+pub fn revoke_token(conn: &mut PgConn, user_uuid: Uuid, token_id: Uuid) -> anyhow::Result<bool> {
+    let deleted = delete(
+        api_tokens::table
+            .filter(api_tokens::id.eq(token_id))
+            .filter(api_tokens::user_id.eq(user_uuid)),
+    )
+    .execute(conn)
+    .context("Failed to revoke API token")?;
+    Ok(deleted > 0)
+}
+
+/// Deletes every token of the user, e.g. once their password was reset
+pub fn revoke_all(conn: &mut PgConn, user_uuid: Uuid) -> anyhow::Result<usize> {
+    delete(api_tokens::table.filter(api_tokens::user_id.eq(user_uuid)))
+        .execute(conn)
+        .context("Failed to revoke API tokens")
+}
+
+/// Finds the owner of an unexpired token and marks the token as used
+pub fn authenticate(conn: &mut PgConn, token: &str) -> Result<(ApiToken, User), AuthError> {
+    let now = SystemTime::now();
+    let api_token = update(
+        api_tokens::table
+            .filter(api_tokens::token_hash.eq(hash_token(token)))
+            .filter(
+                api_tokens::expires_at
+                    .is_null()
+                    .or(api_tokens::expires_at.gt(now)),
+            ),
+    )
+    .set(api_tokens::last_used_at.eq(now))
+    .get_result::<ApiToken>(conn)
+    .optional()
+    .context("Failed to fetch API token")?
+    .ok_or(AuthError::InvalidApiToken)?;
+
+    let user = users::table
+        .filter(users::id.eq(api_token.user_id))
+        .first::<User>(conn)
+        .context("Failed to fetch user")?;
+
+    Ok((api_token, user))
+}
+
+/// Checks that a token may make a request, safe methods need [`Scope::Read`], others [`Scope::Write`]
+pub fn check_scope(api_token: &ApiToken, safe: bool) -> Result<(), AuthError> {
+    let needed = if safe { Scope::Read } else { Scope::Write };
+    if !api_token.scopes.contains(&needed) {
+        println!(
+            "API token {} lacks the {} scope",
+            api_token.id,
+            needed.as_str()
+        );
+        return Err(AuthError::InsufficientScope);
+    }
+    Ok(())
+}
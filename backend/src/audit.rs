@@ -0,0 +1,27 @@
+use crate::{models::NewAuditEntry, schema::audit_log};
+use anyhow::Context;
+use diesel::{insert_into, prelude::*};
+
+/// Too many failed logins for one account
+pub const ACCOUNT_LOCKED: &str = "account_locked";
+/// Too many failed logins from one address
+pub const IP_LOCKED: &str = "ip_locked";
+/// System admin started acting as another user
+pub const IMPERSONATION_STARTED: &str = "impersonation_started";
+/// Request made by a system admin acting as another user
+pub const IMPERSONATED_REQUEST: &str = "impersonated_request";
+/// Existing user logged in with single sign-on for the first time
+pub const OIDC_LINKED: &str = "oidc_linked";
+/// User created at the first single sign-on login
+pub const OIDC_PROVISIONED: &str = "oidc_provisioned";
+/// User deleted their account, which was anonymised
+pub const ACCOUNT_DELETED: &str = "account_deleted";
+
+pub fn record(conn: &mut PgConnection, entry: NewAuditEntry) -> anyhow::Result<()> {
+    println!("Audit: {} {}", entry.event, entry.details);
+    insert_into(audit_log::table)
+        .values(&entry)
+        .execute(conn)
+        .context("Failed to write audit entry")?;
+    Ok(())
+}
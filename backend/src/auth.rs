@@ -1,19 +1,23 @@
 use self::schema::sessions::dsl::*;
 use self::schema::users::dsl::*;
-use crate::schema::{sessions, users};
+use crate::schema::{sessions, user_tokens, users};
 use crate::{
-    models::{NewUser, Session, User},
+    api_tokens, audit,
+    config::{PasswordConfig, SessionConfig},
+    models::{NewAuditEntry, NewUser, NewUserToken, Role, Session, User, UserToken},
     schema,
 };
 use anyhow::{Context, Result};
-use diesel::{delete, insert_into, update};
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+use diesel::{delete, insert_into, pg::Pg, sql_types::Bool, update};
 use diesel::{
     prelude::*,
     r2d2::{ConnectionManager, PooledConnection},
 };
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, net::IpAddr, time::SystemTime};
 use thiserror::Error;
-use time::Duration;
 use uuid::Uuid;
 
 pub type PgConn = PooledConnection<ConnectionManager<PgConnection>>;
@@ -28,21 +32,99 @@ pub enum AuthError {
     WeakPassword,
     #[error("Wrong password")]
     IncorrectPassword,
+    #[error("Invalid login or password")]
+    InvalidCredentials,
+    #[error("Too many failed attempts, try again later")]
+    TooManyAttempts,
     #[error("Session expired")]
     SessionExpired,
+    #[error("Invalid session")]
+    InvalidSession,
+    #[error("Insufficient permissions")]
+    Forbidden,
+    #[error("Token is invalid or expired")]
+    InvalidToken,
+    #[error("API token is invalid or expired")]
+    InvalidApiToken,
+    #[error("API token lacks the required scope")]
+    InsufficientScope,
+    #[error("This action requires logging in with a password")]
+    SessionRequired,
+    #[error("Email address is not verified")]
+    EmailNotVerified,
+    #[error("Two-factor authentication is already enabled")]
+    TwoFactorAlreadyEnabled,
+    #[error("Two-factor authentication is not enabled")]
+    TwoFactorNotEnabled,
+    #[error("Invalid two-factor authentication code")]
+    InvalidTwoFactorCode,
+    #[error("Two-factor authentication has to be enabled first")]
+    TwoFactorRequired,
+    #[error("Single sign-on login failed")]
+    ExternalLoginFailed,
+    #[error("Not allowed while impersonating another user")]
+    ImpersonationForbidden,
+    #[error("Invitation code is invalid, expired or already used")]
+    InvalidInvitation,
     #[error(transparent)]
     Unexpected(#[from] anyhow::Error),
 }
 
-fn hash_pass(pass: &str) -> anyhow::Result<String> {
-    let config = argon2::Config::default();
-    argon2::hash_encoded(pass.as_bytes(), random_salt().as_bytes(), &config)
+impl From<diesel::result::Error> for AuthError {
+    fn from(e: diesel::result::Error) -> Self {
+        AuthError::Unexpected(anyhow::Error::new(e).context("Database query failed"))
+    }
+}
+
+/// Purpose of a [`UserToken`] used to reset a forgotten password
+pub const PASSWORD_RESET: &str = "password_reset";
+/// Purpose of a [`UserToken`] mailed at registration to confirm the email address
+pub const EMAIL_VERIFICATION: &str = "email_verification";
+/// Purpose of a [`UserToken`] mailed to a new address the user wants to switch to
+pub const EMAIL_CHANGE: &str = "email_change";
+
+const SALT_LEN: usize = 16;
+
+pub(crate) fn hash_pass(pass: &str, config: &PasswordConfig) -> anyhow::Result<String> {
+    let config = argon2::Config {
+        variant: argon2::Variant::Argon2id,
+        version: argon2::Version::Version13,
+        mem_cost: config.memory_kib,
+        time_cost: config.iterations,
+        lanes: config.parallelism,
+        ..argon2::Config::default()
+    };
+    argon2::hash_encoded(pass.as_bytes(), &random_salt(), &config)
         .context("Failed to hash a password.")
 }
 
-fn random_salt() -> String {
-    let mut rng = thread_rng();
-    (0..8).map(|_| rng.sample(Alphanumeric) as char).collect()
+fn random_salt() -> [u8; SALT_LEN] {
+    thread_rng().gen()
+}
+
+/// Whether a hash was made with other parameters than the configured ones, or with
+/// a shorter salt. Such hashes look like `$argon2i$v=19$m=4096,t=3,p=1$salt$hash`.
+fn needs_rehash(encoded: &str, config: &PasswordConfig) -> bool {
+    let parts: Vec<&str> = encoded.split('$').collect();
+    let [_, variant, version, params, salt, _] = parts.as_slice() else {
+        return true;
+    };
+    let expected = format!(
+        "m={},t={},p={}",
+        config.memory_kib, config.iterations, config.parallelism
+    );
+    let salt_len = STANDARD_NO_PAD.decode(salt).map_or(0, |salt| salt.len());
+
+    *variant != "argon2id" || *version != "v=19" || *params != expected || salt_len < SALT_LEN
+}
+
+pub(crate) fn random_token() -> String {
+    let bytes: [u8; 32] = thread_rng().gen();
+    hex::encode(bytes)
+}
+
+pub(crate) fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
 }
 
 fn is_strong(user_password: &str, user_inputs: &[&str]) -> bool {
@@ -58,7 +140,8 @@ pub fn try_create_new_user(
     new_login: &str,
     new_email: &str,
     new_password: &str,
-) -> Result<(), AuthError> {
+    config: &PasswordConfig,
+) -> Result<User, AuthError> {
     println!("Trying to create new user");
 
     if get_by_login(conn, new_login)?.is_some() {
@@ -79,17 +162,16 @@ pub fn try_create_new_user(
     let new_user = NewUser {
         login: new_login,
         email: new_email,
-        password: &hash_pass(new_password)?,
+        password: &hash_pass(new_password, config)?,
     };
 
-    let user_uuid = insert_into(users)
+    let user = insert_into(users)
         .values(vec![&new_user])
-        .returning(users::id)
-        .get_result::<uuid::Uuid>(conn)
+        .get_result::<User>(conn)
         .context("Failed to insert user")?;
 
-    println!("Created user with uuid: {}", user_uuid);
-    Ok(())
+    println!("Created user with uuid: {}", user.id);
+    Ok(user)
 }
 
 pub fn try_change_pass(
@@ -97,11 +179,12 @@ pub fn try_change_pass(
     user_login: &str,
     pass: &str,
     new_pass: &str,
+    config: &PasswordConfig,
 ) -> Result<(), AuthError> {
     println!("Trying to change user password");
     let res = get_by_login(conn, user_login)?;
 
-    if res == None {
+    if res.is_none() {
         println!("User does not exist");
         return Err(AuthError::UserNotFound);
     }
@@ -121,18 +204,159 @@ pub fn try_change_pass(
     }
 
     update(users.filter(login.eq(user_login)))
-        .set(password.eq(hash_pass(new_pass)?))
+        .set(password.eq(hash_pass(new_pass, config)?))
         .get_result::<User>(conn)
         .context("Failed to update user.")?;
 
     Ok(())
 }
 
+/// Creates a single-use token, replacing the user's older unused tokens with the same purpose.
+/// Returns the plain token, which is never stored.
+pub fn issue_token(
+    conn: &mut PgConn,
+    user_uuid: Uuid,
+    token_purpose: &str,
+    lifetime: time::Duration,
+) -> anyhow::Result<String> {
+    let token = random_token();
+
+    delete(
+        user_tokens::table
+            .filter(user_tokens::user_id.eq(user_uuid))
+            .filter(user_tokens::purpose.eq(token_purpose))
+            .filter(user_tokens::used_at.is_null()),
+    )
+    .execute(conn)
+    .context("Failed to delete old tokens")?;
+
+    insert_into(user_tokens::table)
+        .values(&NewUserToken {
+            user_id: user_uuid,
+            purpose: token_purpose,
+            token_hash: &hash_token(&token),
+            expires_at: SystemTime::now() + lifetime,
+        })
+        .execute(conn)
+        .context("Failed to insert token")?;
+
+    Ok(token)
+}
+
+/// Marks a valid token as used, so that it can't be used again
+pub(crate) fn consume_token(
+    conn: &mut PgConnection,
+    token_purpose: &str,
+    token: &str,
+) -> Result<UserToken, AuthError> {
+    let now = SystemTime::now();
+    update(
+        user_tokens::table
+            .filter(user_tokens::token_hash.eq(hash_token(token)))
+            .filter(user_tokens::purpose.eq(token_purpose))
+            .filter(user_tokens::used_at.is_null())
+            .filter(user_tokens::expires_at.gt(now)),
+    )
+    .set(user_tokens::used_at.eq(now))
+    .get_result::<UserToken>(conn)
+    .optional()
+    .context("Failed to consume token")?
+    .ok_or(AuthError::InvalidToken)
+}
+
+/// Returns the user with given email and a password reset token to mail them,
+/// or `None` if nobody uses this email
+pub fn request_password_reset(
+    conn: &mut PgConn,
+    user_email: &str,
+    lifetime: time::Duration,
+) -> anyhow::Result<Option<(User, String)>> {
+    let Some(user) = get_by_email(conn, user_email)? else {
+        println!("Password reset requested for unknown email");
+        return Ok(None);
+    };
+
+    let token = issue_token(conn, user.id, PASSWORD_RESET, lifetime)?;
+    Ok(Some((user, token)))
+}
+
+/// Sets a new password using a reset token and logs the user out everywhere
+pub fn try_reset_pass(
+    conn: &mut PgConn,
+    token: &str,
+    new_pass: &str,
+    config: &PasswordConfig,
+) -> Result<(), AuthError> {
+    conn.transaction(|conn| {
+        let token = consume_token(conn, PASSWORD_RESET, token)?;
+        let user = users
+            .filter(users::id.eq(token.user_id))
+            .first::<User>(conn)
+            .context("Failed to fetch user")?;
+
+        if !is_strong(new_pass, &[&user.login, &user.email]) {
+            println!("Too weak password");
+            return Err(AuthError::WeakPassword);
+        }
+
+        update(users.filter(users::id.eq(user.id)))
+            .set(password.eq(hash_pass(new_pass, config)?))
+            .execute(conn)
+            .context("Failed to update password")?;
+
+        delete(sessions.filter(user_id.eq(user.id)))
+            .execute(conn)
+            .context("Failed to delete sessions")?;
+        // whoever knew the old password may have made tokens with it
+        api_tokens::revoke_all(conn, user.id)?;
+
+        Ok(())
+    })
+}
+
+/// Returns an unverified user with given email and a new verification token to mail them,
+/// or `None` if there is no such user
+pub fn request_email_verification(
+    conn: &mut PgConn,
+    user_email: &str,
+    lifetime: time::Duration,
+) -> anyhow::Result<Option<(User, String)>> {
+    let user = users
+        .filter(email.eq(user_email))
+        .filter(verified_at.is_null())
+        .first::<User>(conn)
+        .optional()
+        .context("Failed to fetch unverified user")?;
+    let Some(user) = user else {
+        return Ok(None);
+    };
+
+    let token = issue_token(conn, user.id, EMAIL_VERIFICATION, lifetime)?;
+    Ok(Some((user, token)))
+}
+
+/// Confirms the user's email address using a verification token
+pub fn verify_email(conn: &mut PgConn, token: &str) -> Result<User, AuthError> {
+    conn.transaction(|conn| {
+        let token = consume_token(conn, EMAIL_VERIFICATION, token)?;
+        let user = update(users.filter(users::id.eq(token.user_id)))
+            .set(verified_at.eq(SystemTime::now()))
+            .get_result::<User>(conn)
+            .context("Failed to verify user")?;
+
+        println!("Verified email of user {}", user.login);
+        Ok(user)
+    })
+}
+
+/// Checks the password and replaces its hash if the configured parameters changed since
+/// it was made, so that they can be tightened without making everyone reset their password
 pub fn login_user(
     conn: &mut PgConn,
     user_login: &str,
     user_password: &str,
-) -> Result<Uuid, AuthError> {
+    config: &PasswordConfig,
+) -> Result<User, AuthError> {
     let res = users.filter(login.eq(user_login)).first::<User>(conn);
 
     match res {
@@ -140,26 +364,63 @@ pub fn login_user(
             if argon2::verify_encoded(&user.password, user_password.as_bytes())
                 .context("Failed to verify password.")?
             {
-                return Ok(user.id);
+                let user = if needs_rehash(&user.password, config) {
+                    println!("Upgrading password hash of user {}", user.id);
+                    update(users.filter(users::id.eq(user.id)))
+                        .set(password.eq(hash_pass(user_password, config)?))
+                        .get_result::<User>(conn)
+                        .context("Failed to upgrade password hash")?
+                } else {
+                    user
+                };
+                if user.verified_at.is_none() {
+                    println!("Email not verified!");
+                    return Err(AuthError::EmailNotVerified);
+                }
+                return Ok(user);
             }
             println!("Incorrect password!");
-            Err(AuthError::IncorrectPassword)
+            Err(AuthError::InvalidCredentials)
         }
 
         Err(_) => {
             println!("Login not found!");
-            Err(AuthError::UserNotFound)
+            // takes as long as checking a real password, so that logins can't be told apart by timing
+            hash_pass(user_password, config)?;
+            Err(AuthError::InvalidCredentials)
         }
     }
 }
 
-pub fn try_get_session(conn: &mut PgConn, session_id: Uuid) -> Result<User, AuthError> {
-    // need to fetch corresponding User
-    // finds a corresponding session id
-    let session = sessions
-        .filter(sessions::id.eq(session_id))
-        .first::<Session>(conn)
-        .context("Failed to fetch session")?;
+pub fn try_get_session(
+    conn: &mut PgConn,
+    session_id: Uuid,
+    config: &SessionConfig,
+) -> Result<(Session, User), AuthError> {
+    // finds a corresponding session id and refreshes it, unless it has expired
+    let session = update(
+        sessions
+            .filter(sessions::id.eq(session_id))
+            .filter(is_active(config)),
+    )
+    .set(last_seen.eq(SystemTime::now()))
+    .get_result::<Session>(conn)
+    .optional()
+    .context("Failed to refresh session")?;
+
+    let session = match session {
+        Some(session) => session,
+        None => {
+            let deleted = delete(sessions.filter(sessions::id.eq(session_id)))
+                .execute(conn)
+                .context("Failed to delete session")?;
+            if deleted > 0 {
+                println!("Session expired!");
+                return Err(AuthError::SessionExpired);
+            }
+            return Err(AuthError::InvalidSession);
+        }
+    };
 
     // finds a user with this session id
     let user = users
@@ -167,32 +428,216 @@ pub fn try_get_session(conn: &mut PgConn, session_id: Uuid) -> Result<User, Auth
         .first::<User>(conn)
         .context("Failed to fetch user")?;
 
-    let duration = session
-        .iat
-        .elapsed()
-        .context("Failed to check session time")?;
+    Ok((session, user))
+}
 
-    // verifies whether the session hasn't expired
-    println!("Session time: {:?}", duration);
-    if duration < Duration::minutes(10) {
-        return Ok(user);
-    }
-    println!("Session expired!");
-    delete(sessions.filter(sessions::id.eq(session.id)))
-        .execute(conn)
-        .context("Failed to delete session")?;
+/// Matches sessions which are within both their idle and absolute timeout
+fn is_active(
+    config: &SessionConfig,
+) -> Box<dyn BoxableExpression<sessions::table, Pg, SqlType = Bool>> {
+    let now = SystemTime::now();
+    let (idle, absolute) = config.timeouts(false);
+    let (remember_idle, remember_absolute) = config.timeouts(true);
 
-    Err(AuthError::SessionExpired)
+    Box::new(
+        remember
+            .eq(false)
+            .and(last_seen.gt(now - idle))
+            .and(iat.gt(now - absolute))
+            .or(remember
+                .eq(true)
+                .and(last_seen.gt(now - remember_idle))
+                .and(iat.gt(now - remember_absolute))),
+    )
 }
 
-pub fn create_session(conn: &mut PgConn, user_uuid: Uuid) -> anyhow::Result<Uuid> {
+pub fn create_session(
+    conn: &mut PgConn,
+    user_uuid: Uuid,
+    remember_me: bool,
+) -> anyhow::Result<Uuid> {
     insert_into(sessions::table)
-        .values(user_id.eq(user_uuid))
+        .values((user_id.eq(user_uuid), remember.eq(remember_me)))
         .returning(sessions::id)
         .get_result::<Uuid>(conn)
         .context("Failed to create session")
 }
 
+/// Creates a session of `target_id` for a system admin to see exactly what the user sees.
+/// Other system admins can't be impersonated.
+pub fn start_impersonation(
+    conn: &mut PgConn,
+    impersonator: &User,
+    target_id: Uuid,
+    ip: IpAddr,
+) -> Result<(Uuid, User), AuthError> {
+    authorize(impersonator, &[], None)?;
+
+    conn.transaction(|conn| {
+        let target = users
+            .filter(users::id.eq(target_id))
+            .first::<User>(conn)
+            .optional()
+            .context("Failed to fetch user")?
+            .ok_or(AuthError::UserNotFound)?;
+        if target.role == Role::SystemAdmin || target.id == impersonator.id {
+            println!("User {} can't be impersonated", target.login);
+            return Err(AuthError::Forbidden);
+        }
+
+        let session_id = insert_into(sessions::table)
+            .values((
+                user_id.eq(target.id),
+                remember.eq(false),
+                impersonator_id.eq(impersonator.id),
+            ))
+            .returning(sessions::id)
+            .get_result::<Uuid>(conn)
+            .context("Failed to create session")?;
+        audit::record(
+            conn,
+            NewAuditEntry {
+                event: audit::IMPERSONATION_STARTED,
+                user_id: Some(impersonator.id),
+                ip: Some(ip.to_string()),
+                details: format!("{} impersonates {}", impersonator.login, target.login),
+            },
+        )?;
+
+        Ok((session_id, target))
+    })
+}
+
+/// Whether the session belongs to an admin impersonating its user
+pub fn is_impersonation(conn: &mut PgConn, session_id: Uuid) -> anyhow::Result<bool> {
+    let impersonator = sessions
+        .filter(sessions::id.eq(session_id))
+        .select(impersonator_id)
+        .first::<Option<Uuid>>(conn)
+        .optional()
+        .context("Failed to fetch session")?;
+    Ok(matches!(impersonator, Some(Some(_))))
+}
+
+/// Checks whether `user` has one of `roles` and belongs to `school`.
+/// System admins are allowed everywhere.
+pub fn authorize(user: &User, roles: &[Role], school: Option<Uuid>) -> Result<(), AuthError> {
+    if user.role == Role::SystemAdmin {
+        return Ok(());
+    }
+
+    if !roles.contains(&user.role) {
+        println!("User {} has insufficient role", user.login);
+        return Err(AuthError::Forbidden);
+    }
+
+    match school {
+        Some(school_uuid) if user.school_id != Some(school_uuid) => {
+            println!(
+                "User {} does not belong to school {}",
+                user.login, school_uuid
+            );
+            Err(AuthError::Forbidden)
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn set_role(
+    conn: &mut PgConn,
+    user_uuid: Uuid,
+    new_role: Role,
+    new_school_id: Option<Uuid>,
+) -> Result<User, AuthError> {
+    update(users.filter(users::id.eq(user_uuid)))
+        .set((role.eq(new_role), users::school_id.eq(new_school_id)))
+        .get_result::<User>(conn)
+        .optional()
+        .context("Failed to update user role")?
+        .ok_or(AuthError::UserNotFound)
+}
+
+pub fn delete_session(conn: &mut PgConn, session_id: Uuid) -> anyhow::Result<()> {
+    delete(sessions.filter(sessions::id.eq(session_id)))
+        .execute(conn)
+        .context("Failed to delete session")?;
+    Ok(())
+}
+
+/// Deletes one of the user's sessions, returns `false` if the user has no such session
+pub fn revoke_user_session(
+    conn: &mut PgConn,
+    user_uuid: Uuid,
+    session_id: Uuid,
+) -> anyhow::Result<bool> {
+    let deleted = delete(
+        sessions
+            .filter(sessions::id.eq(session_id))
+            .filter(user_id.eq(user_uuid)),
+    )
+    .execute(conn)
+    .context("Failed to revoke session")?;
+    Ok(deleted > 0)
+}
+
+/// Deletes up to `batch_size` expired sessions, returns how many were deleted
+pub fn delete_expired_sessions(
+    conn: &mut PgConn,
+    config: &SessionConfig,
+    batch_size: i64,
+) -> anyhow::Result<usize> {
+    let expired = sessions
+        .select(sessions::id)
+        .filter(diesel::dsl::not(is_active(config)))
+        .limit(batch_size)
+        .load::<Uuid>(conn)
+        .context("Failed to fetch expired sessions")?;
+
+    delete(sessions.filter(sessions::id.eq_any(expired)))
+        .execute(conn)
+        .context("Failed to delete expired sessions")
+}
+
+/// Logs the user out everywhere, returns the number of deleted sessions
+pub fn delete_user_sessions(conn: &mut PgConn, user_uuid: Uuid) -> anyhow::Result<usize> {
+    delete(sessions.filter(user_id.eq(user_uuid)))
+        .execute(conn)
+        .context("Failed to delete user sessions")
+}
+
+/// Active sessions of a user, newest first, with the login of the admin
+/// impersonating the user in that session
+pub fn get_user_sessions(
+    conn: &mut PgConn,
+    user_uuid: Uuid,
+    config: &SessionConfig,
+) -> anyhow::Result<Vec<(Session, Option<String>)>> {
+    let active = sessions
+        .filter(user_id.eq(user_uuid))
+        .filter(is_active(config))
+        .order(iat.desc())
+        .load::<Session>(conn)
+        .context("Failed to fetch user sessions")?;
+
+    let impersonators: HashMap<Uuid, String> = users
+        .filter(users::id.eq_any(active.iter().filter_map(|session| session.impersonator_id)))
+        .select((users::id, login))
+        .load::<(Uuid, String)>(conn)
+        .context("Failed to fetch impersonators")?
+        .into_iter()
+        .collect();
+
+    Ok(active
+        .into_iter()
+        .map(|session| {
+            let impersonator = session
+                .impersonator_id
+                .and_then(|admin| impersonators.get(&admin).cloned());
+            (session, impersonator)
+        })
+        .collect())
+}
+
 pub fn get_by_login(conn: &mut PgConn, user_login: &str) -> anyhow::Result<Option<User>> {
     users
         .filter(login.eq(user_login))
@@ -201,6 +646,14 @@ pub fn get_by_login(conn: &mut PgConn, user_login: &str) -> anyhow::Result<Optio
         .context("Failed to fetch user by login")
 }
 
+pub fn get_by_id(conn: &mut PgConn, user_uuid: Uuid) -> anyhow::Result<Option<User>> {
+    users
+        .filter(users::id.eq(user_uuid))
+        .first::<User>(conn)
+        .optional()
+        .context("Failed to fetch user by id")
+}
+
 pub fn get_by_email(conn: &mut PgConn, user_email: &str) -> anyhow::Result<Option<User>> {
     users
         .filter(email.eq(user_email))
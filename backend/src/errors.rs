@@ -0,0 +1,316 @@
+use crate::{administration, auth::AuthError};
+use axum::{
+    extract::{
+        path::ErrorKind,
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use diesel::r2d2::PoolError;
+use serde::Serialize;
+
+/// Error returned by every API handler, rendered as
+/// `{"code": "...", "message": "...", "fields": [...]}`
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    fields: Vec<FieldError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'static str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    fields: &'a [FieldError],
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn with_field(mut self, field: impl Into<String>, message: impl Into<String>) -> Self {
+        self.fields.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    pub fn unauthorized() -> Self {
+        Self::new(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "Authentication required",
+        )
+    }
+
+    pub fn forbidden() -> Self {
+        Self::new(
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "Insufficient permissions",
+        )
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", "Record not found")
+    }
+
+    pub fn weak_password(field: &'static str) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "weak_password",
+            "Password is too weak",
+        )
+        .with_field(field, "Use a longer or less predictable password")
+    }
+
+    pub fn internal(e: impl std::fmt::Display) -> Self {
+        println!("Internal error: {e}");
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Internal server error",
+        )
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            code: self.code,
+            message: &self.message,
+            fields: &self.fields,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(e: AuthError) -> Self {
+        let message = e.to_string();
+        match e {
+            AuthError::UserAlreadyExists => {
+                Self::new(StatusCode::CONFLICT, "user_already_exists", message)
+            }
+            AuthError::UserNotFound => Self::new(StatusCode::NOT_FOUND, "user_not_found", message),
+            AuthError::WeakPassword => Self::weak_password("password"),
+            AuthError::IncorrectPassword => {
+                Self::new(StatusCode::UNAUTHORIZED, "incorrect_password", message)
+            }
+            AuthError::InvalidCredentials => {
+                Self::new(StatusCode::UNAUTHORIZED, "invalid_credentials", message)
+            }
+            AuthError::TooManyAttempts => {
+                Self::new(StatusCode::TOO_MANY_REQUESTS, "too_many_attempts", message)
+            }
+            AuthError::SessionExpired => {
+                Self::new(StatusCode::UNAUTHORIZED, "session_expired", message)
+            }
+            AuthError::InvalidSession => {
+                Self::new(StatusCode::UNAUTHORIZED, "invalid_session", message)
+            }
+            AuthError::Forbidden => Self::forbidden(),
+            AuthError::InvalidToken => Self::new(StatusCode::BAD_REQUEST, "invalid_token", message),
+            AuthError::InvalidApiToken => {
+                Self::new(StatusCode::UNAUTHORIZED, "invalid_api_token", message)
+            }
+            AuthError::InsufficientScope => {
+                Self::new(StatusCode::FORBIDDEN, "insufficient_scope", message)
+            }
+            AuthError::SessionRequired => {
+                Self::new(StatusCode::FORBIDDEN, "session_required", message)
+            }
+            AuthError::EmailNotVerified => {
+                Self::new(StatusCode::FORBIDDEN, "email_not_verified", message)
+            }
+            AuthError::TwoFactorAlreadyEnabled => {
+                Self::new(StatusCode::CONFLICT, "two_factor_already_enabled", message)
+            }
+            AuthError::TwoFactorNotEnabled => {
+                Self::new(StatusCode::BAD_REQUEST, "two_factor_not_enabled", message)
+            }
+            AuthError::InvalidTwoFactorCode => {
+                Self::new(StatusCode::UNAUTHORIZED, "invalid_two_factor_code", message)
+            }
+            AuthError::TwoFactorRequired => {
+                Self::new(StatusCode::FORBIDDEN, "two_factor_required", message)
+            }
+            AuthError::ExternalLoginFailed => {
+                Self::new(StatusCode::UNAUTHORIZED, "external_login_failed", message)
+            }
+            AuthError::ImpersonationForbidden => {
+                Self::new(StatusCode::FORBIDDEN, "impersonation_forbidden", message)
+            }
+            AuthError::InvalidInvitation => {
+                Self::new(StatusCode::BAD_REQUEST, "invalid_invitation", message)
+            }
+            AuthError::Unexpected(e) => Self::internal(format!("{e:#}")),
+        }
+    }
+}
+
+impl From<administration::Error> for ApiError {
+    fn from(e: administration::Error) -> Self {
+        let message = e.to_string();
+        match e {
+            administration::Error::NotFound => Self::not_found(),
+            administration::Error::AlreadyExists(_) => {
+                Self::new(StatusCode::CONFLICT, "already_exists", message)
+            }
+            administration::Error::ForeignKeyViolation(_) => {
+                Self::new(StatusCode::CONFLICT, "foreign_key_violation", message)
+            }
+            administration::Error::NothingToUpdate => {
+                Self::new(StatusCode::BAD_REQUEST, "nothing_to_update", message)
+            }
+            administration::Error::NotOnScale(_) => {
+                Self::new(StatusCode::BAD_REQUEST, "not_on_scale", message)
+            }
+            administration::Error::InvalidScale(_) => {
+                Self::new(StatusCode::BAD_REQUEST, "invalid_scale", message)
+            }
+            administration::Error::InvalidCorrection(_) => {
+                Self::new(StatusCode::BAD_REQUEST, "invalid_correction", message)
+            }
+            administration::Error::ReasonRequired => {
+                Self::new(StatusCode::BAD_REQUEST, "reason_required", message)
+            }
+            administration::Error::SubjectRequired => {
+                Self::new(StatusCode::BAD_REQUEST, "subject_required", message)
+            }
+            administration::Error::NotInClass(_) => {
+                Self::new(StatusCode::BAD_REQUEST, "not_in_class", message)
+            }
+            administration::Error::InvalidWeight => {
+                Self::new(StatusCode::BAD_REQUEST, "invalid_weight", message)
+                    .with_field("weight", "Use a whole number of at least 1")
+            }
+            administration::Error::Unexpected(e) => Self::internal(format!("{e:#}")),
+        }
+    }
+}
+
+/// Field named by a serde error like "missing field `reason` at line 1 column 2"
+fn named_field(error: &str) -> Option<&str> {
+    if !["missing field", "unknown field", "duplicate field"]
+        .iter()
+        .any(|prefix| error.starts_with(prefix))
+    {
+        return None;
+    }
+    error.split('`').nth(1)
+}
+
+/// Rejections of malformed requests, see [`routes::extract`](crate::routes::extract)
+impl From<JsonRejection> for ApiError {
+    fn from(e: JsonRejection) -> Self {
+        match e {
+            JsonRejection::JsonDataError(e) => {
+                let detail = std::error::Error::source(&e)
+                    .map(ToString::to_string)
+                    .unwrap_or_default();
+                let error = Self::new(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "invalid_body",
+                    format!("Request body doesn't match the expected fields: {detail}"),
+                );
+                match named_field(&detail) {
+                    Some(field) => error.with_field(field, detail.clone()),
+                    None => error,
+                }
+            }
+            JsonRejection::JsonSyntaxError(_) => Self::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_json",
+                "Request body is not valid JSON",
+            ),
+            JsonRejection::MissingJsonContentType(_) => Self::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                "Expected a request with `Content-Type: application/json`",
+            ),
+            e => Self::new(StatusCode::BAD_REQUEST, "invalid_body", e.to_string()),
+        }
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(e: PathRejection) -> Self {
+        match e {
+            PathRejection::FailedToDeserializePathParams(e) => {
+                let message = e.to_string();
+                match e.into_kind() {
+                    ErrorKind::WrongNumberOfParameters { .. }
+                    | ErrorKind::UnsupportedType { .. } => Self::internal(message),
+                    ErrorKind::ParseErrorAtKey { key, .. } => {
+                        Self::new(StatusCode::BAD_REQUEST, "invalid_path", message.clone())
+                            .with_field(key, message)
+                    }
+                    _ => Self::new(StatusCode::BAD_REQUEST, "invalid_path", message),
+                }
+            }
+            e => Self::internal(e),
+        }
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(e: QueryRejection) -> Self {
+        let message = e.to_string();
+        let detail = message
+            .strip_prefix("Failed to deserialize query string: ")
+            .unwrap_or(&message);
+        let error = Self::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_query",
+            message.clone(),
+        );
+        match named_field(detail) {
+            Some(field) => error.with_field(field, detail),
+            None => error,
+        }
+    }
+}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(e: diesel::result::Error) -> Self {
+        administration::Error::from(e).into()
+    }
+}
+
+impl From<PoolError> for ApiError {
+    fn from(e: PoolError) -> Self {
+        println!("Failed to get database connection: {e}");
+        Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "database_unavailable",
+            "Database is unavailable",
+        )
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{
+    administration::{Error, PgConn},
+    models::{GradeHistoryEntry, GradeSymbol},
+    schema::{grade_history, grade_symbols},
+};
+use diesel::{delete, insert_into, prelude::*, sql_query, sql_types::Text};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Polish 1–6 scale, used by schools which didn't define their own. A plus adds half
+/// a grade and a minus takes away a quarter, so "4+" is 4.5 and "5-" is 4.75.
+/// "np" (unprepared), "bz" (missing work) and a bare "+" or "-" for activity
+/// are noted without counting towards averages.
+pub fn polish_scale(school_id: Uuid) -> Vec<GradeSymbol> {
+    let mut scale = Vec::new();
+    for grade in 1..=6 {
+        let value = f64::from(grade);
+        if grade > 1 {
+            scale.push((format!("{grade}-"), Some(value - 0.25), true));
+        }
+        scale.push((grade.to_string(), Some(value), true));
+        if grade < 6 {
+            scale.push((format!("{grade}+"), Some(value + 0.5), true));
+        }
+    }
+    for mark in ["np", "bz", "+", "-"] {
+        scale.push((mark.to_string(), None, false));
+    }
+
+    scale
+        .into_iter()
+        .map(|(symbol, value, counts)| GradeSymbol {
+            school_id,
+            symbol,
+            value,
+            counts,
+        })
+        .collect()
+}
+
+/// Scale of the school, the default one if it has none
+pub fn get_scale(conn: &mut PgConn, school_uuid: Uuid) -> Result<Vec<GradeSymbol>, Error> {
+    let scale = grade_symbols::table
+        .filter(grade_symbols::school_id.eq(school_uuid))
+        .order((
+            grade_symbols::value.asc().nulls_last(),
+            grade_symbols::symbol,
+        ))
+        .load::<GradeSymbol>(conn)?;
+    if scale.is_empty() {
+        return Ok(polish_scale(school_uuid));
+    }
+    Ok(scale)
+}
+
+/// Replaces the school's scale, grades given earlier keep their symbols and values
+pub fn set_scale(
+    conn: &mut PgConn,
+    school_uuid: Uuid,
+    scale: Vec<GradeSymbol>,
+) -> Result<Vec<GradeSymbol>, Error> {
+    if scale.is_empty() {
+        return Err(Error::InvalidScale("Scale has no symbols".to_string()));
+    }
+    let mut seen = HashSet::new();
+    for entry in &scale {
+        if entry.symbol.trim().is_empty() {
+            return Err(Error::InvalidScale("Symbols can't be blank".to_string()));
+        }
+        if !seen.insert(entry.symbol.as_str()) {
+            return Err(Error::InvalidScale(format!(
+                "Symbol {} appears more than once",
+                entry.symbol
+            )));
+        }
+        if entry.counts && entry.value.is_none() {
+            return Err(Error::InvalidScale(format!(
+                "Symbol {} counts towards averages without a value",
+                entry.symbol
+            )));
+        }
+    }
+
+    let scale: Vec<GradeSymbol> = scale
+        .into_iter()
+        .map(|entry| GradeSymbol {
+            school_id: school_uuid,
+            ..entry
+        })
+        .collect();
+    conn.transaction(|conn| {
+        delete(grade_symbols::table.filter(grade_symbols::school_id.eq(school_uuid)))
+            .execute(conn)?;
+        insert_into(grade_symbols::table)
+            .values(&scale)
+            .execute(conn)?;
+        Ok::<_, Error>(())
+    })?;
+    get_scale(conn, school_uuid)
+}
+
+/// Looks the symbol up on the school's scale
+pub fn find_symbol(
+    conn: &mut PgConn,
+    school_uuid: Uuid,
+    symbol: &str,
+) -> Result<GradeSymbol, Error> {
+    get_scale(conn, school_uuid)?
+        .into_iter()
+        .find(|entry| entry.symbol == symbol)
+        .ok_or_else(|| Error::NotOnScale(symbol.to_string()))
+}
+
+/// Names the user changing grades in the current transaction and their reason, the
+/// database picks both up when it writes the grades' history
+pub(crate) fn set_change_author(
+    conn: &mut PgConn,
+    user_uuid: Uuid,
+    reason: Option<&str>,
+) -> Result<(), Error> {
+    sql_query(
+        "select set_config('bibrus.changed_by', $1, true), \
+                set_config('bibrus.change_reason', $2, true)",
+    )
+    .bind::<Text, _>(user_uuid.to_string())
+    .bind::<Text, _>(reason.unwrap_or_default())
+    .execute(conn)?;
+    Ok(())
+}
+
+/// Every change of the grade, oldest first. The history outlives deleted grades.
+pub fn grade_history(conn: &mut PgConn, grade_uuid: Uuid) -> Result<Vec<GradeHistoryEntry>, Error> {
+    let history = grade_history::table
+        .filter(grade_history::grade_id.eq(grade_uuid))
+        .order(grade_history::changed_at)
+        .load::<GradeHistoryEntry>(conn)?;
+    if history.is_empty() {
+        return Err(Error::NotFound);
+    }
+    Ok(history)
+}
@@ -0,0 +1,101 @@
+use crate::{
+    administration::{Error, PgConn},
+    models::{Guardian, NewGuardian, Role, Student, UpdateGuardian, User},
+    schema::{guardians, students, users},
+};
+use diesel::{delete, insert_into, prelude::*, update};
+use uuid::Uuid;
+
+/// Links a user to a student. Users without a school become parents at the student's school.
+pub fn add_guardian(conn: &mut PgConn, guardian: &NewGuardian) -> Result<Guardian, Error> {
+    conn.transaction(|conn| {
+        let guardian = insert_into(guardians::table)
+            .values(guardian)
+            .get_result::<Guardian>(conn)?;
+
+        let user = users::table.find(guardian.user_id).first::<User>(conn)?;
+        if user.school_id.is_none() && user.role != Role::SystemAdmin {
+            let school = students::table
+                .find(guardian.student_id)
+                .select(students::school_id)
+                .first::<Uuid>(conn)?;
+            update(users::table.find(user.id))
+                .set((users::role.eq(Role::Parent), users::school_id.eq(school)))
+                .execute(conn)?;
+        }
+        Ok(guardian)
+    })
+}
+
+pub fn get_guardian(
+    conn: &mut PgConn,
+    user_uuid: Uuid,
+    student_uuid: Uuid,
+) -> Result<Guardian, Error> {
+    guardians::table
+        .find((user_uuid, student_uuid))
+        .first::<Guardian>(conn)
+        .map_err(Error::from)
+}
+
+pub fn list_guardians(
+    conn: &mut PgConn,
+    school: Option<Uuid>,
+    student: Option<Uuid>,
+) -> Result<Vec<Guardian>, Error> {
+    let mut query = guardians::table
+        .inner_join(students::table)
+        .select(guardians::all_columns)
+        .into_boxed();
+    if let Some(school_uuid) = school {
+        query = query.filter(students::school_id.eq(school_uuid));
+    }
+    if let Some(student_uuid) = student {
+        query = query.filter(guardians::student_id.eq(student_uuid));
+    }
+    query.load::<Guardian>(conn).map_err(Error::from)
+}
+
+pub fn update_guardian(
+    conn: &mut PgConn,
+    user_uuid: Uuid,
+    student_uuid: Uuid,
+    changes: &UpdateGuardian,
+) -> Result<Guardian, Error> {
+    update(guardians::table.find((user_uuid, student_uuid)))
+        .set(changes)
+        .get_result::<Guardian>(conn)
+        .map_err(Error::from)
+}
+
+pub fn remove_guardian(
+    conn: &mut PgConn,
+    user_uuid: Uuid,
+    student_uuid: Uuid,
+) -> Result<Guardian, Error> {
+    delete(guardians::table.find((user_uuid, student_uuid)))
+        .get_result::<Guardian>(conn)
+        .map_err(Error::from)
+}
+
+/// Students the user is a guardian of, with the link to each of them
+pub fn list_children(
+    conn: &mut PgConn,
+    user_uuid: Uuid,
+) -> Result<Vec<(Student, Guardian)>, Error> {
+    students::table
+        .inner_join(guardians::table)
+        .filter(guardians::user_id.eq(user_uuid))
+        .order((students::last_name, students::first_name))
+        .select((students::all_columns, guardians::all_columns))
+        .load::<(Student, Guardian)>(conn)
+        .map_err(Error::from)
+}
+
+pub fn is_guardian(conn: &mut PgConn, user_uuid: Uuid, student_uuid: Uuid) -> Result<bool, Error> {
+    diesel::select(diesel::dsl::exists(
+        guardians::table.find((user_uuid, student_uuid)),
+    ))
+    .get_result::<bool>(conn)
+    .map_err(Error::from)
+}
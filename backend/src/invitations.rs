@@ -0,0 +1,193 @@
+use crate::{
+    administration::{self, Error},
+    auth::{hash_token, AuthError, PgConn},
+    models::{Invitation, NewInvitation, Role, Student, Teacher, User},
+    schema::{invitations, students, teachers, users},
+};
+use diesel::{insert_into, prelude::*, update};
+use rand::{seq::SliceRandom, thread_rng};
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// Without characters which are easy to confuse, like `0` and `O`
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 12;
+
+/// Record an invitation attaches an account to
+#[derive(Debug, Clone, Copy)]
+pub enum Target {
+    Student(Uuid),
+    Teacher(Uuid),
+}
+
+/// Code like `ABCD-EFGH-JKLM`, short enough to be typed from a printout
+fn random_code() -> String {
+    let mut rng = thread_rng();
+    let chars: Vec<char> = (0..CODE_LEN)
+        .map(|_| *CODE_ALPHABET.choose(&mut rng).unwrap() as char)
+        .collect();
+    chars
+        .chunks(4)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Accepts codes typed in lowercase or without dashes
+fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Creates an invitation to a record which isn't linked to an account yet,
+/// returns it with the code to hand out
+pub fn create_invitation(
+    conn: &mut PgConn,
+    creator: Uuid,
+    target: Target,
+    lifetime: time::Duration,
+) -> Result<(Invitation, String), Error> {
+    let linked = match target {
+        Target::Student(student) => administration::get_student(conn, student)?.user_id,
+        Target::Teacher(teacher) => administration::get_teacher(conn, teacher)?.user_id,
+    };
+    if linked.is_some() {
+        return Err(Error::AlreadyExists(
+            "Record is already linked to an account".to_string(),
+        ));
+    }
+
+    let code = random_code();
+    let (student_id, teacher_id) = match target {
+        Target::Student(student) => (Some(student), None),
+        Target::Teacher(teacher) => (None, Some(teacher)),
+    };
+    let invitation = insert_into(invitations::table)
+        .values(&NewInvitation {
+            code_hash: &code_hash(&code),
+            student_id,
+            teacher_id,
+            created_by: creator,
+            expires_at: SystemTime::now() + lifetime,
+        })
+        .get_result::<Invitation>(conn)?;
+
+    Ok((invitation, code))
+}
+
+pub fn get_invitation(conn: &mut PgConn, invitation_id: Uuid) -> Result<Invitation, Error> {
+    invitations::table
+        .find(invitation_id)
+        .first::<Invitation>(conn)
+        .map_err(Error::from)
+}
+
+pub fn list_invitations(conn: &mut PgConn, school: Option<Uuid>) -> Result<Vec<Invitation>, Error> {
+    let mut query = invitations::table
+        .left_join(students::table)
+        .left_join(teachers::table)
+        .select(invitations::all_columns)
+        .order(invitations::created_at.desc())
+        .into_boxed();
+    if let Some(school) = school {
+        query = query.filter(
+            students::school_id
+                .eq(school)
+                .or(teachers::school_id.eq(school)),
+        );
+    }
+    query.load::<Invitation>(conn).map_err(Error::from)
+}
+
+pub fn school_of_invitation(conn: &mut PgConn, invitation: &Invitation) -> Result<Uuid, Error> {
+    match (invitation.student_id, invitation.teacher_id) {
+        (Some(student), _) => administration::school_of_student(conn, student),
+        (_, Some(teacher)) => administration::school_of_teacher(conn, teacher),
+        _ => Err(Error::NotFound),
+    }
+}
+
+/// Revokes an invitation which wasn't redeemed yet
+pub fn revoke_invitation(conn: &mut PgConn, invitation_id: Uuid) -> Result<Invitation, Error> {
+    update(
+        invitations::table
+            .find(invitation_id)
+            .filter(invitations::redeemed_at.is_null())
+            .filter(invitations::revoked_at.is_null()),
+    )
+    .set(invitations::revoked_at.eq(SystemTime::now()))
+    .get_result::<Invitation>(conn)
+    .map_err(Error::from)
+}
+
+/// Hash under which a code is stored, the code itself is only shown once
+pub fn code_hash(code: &str) -> String {
+    hash_token(&normalize(code))
+}
+
+/// Attaches the user to the invitation's record. Users without a school
+/// also get the record's school and the matching role.
+pub fn redeem(conn: &mut PgConn, code: &str, user: &User) -> Result<User, AuthError> {
+    redeem_hash(conn, &code_hash(code), user)
+}
+
+/// Like [`redeem`], for a code which was only kept as its [`code_hash`]
+pub fn redeem_hash(conn: &mut PgConn, hash: &str, user: &User) -> Result<User, AuthError> {
+    conn.transaction(|conn| {
+        let invitation = invitations::table
+            .filter(invitations::code_hash.eq(hash))
+            .filter(invitations::redeemed_at.is_null())
+            .filter(invitations::revoked_at.is_null())
+            .filter(invitations::expires_at.gt(SystemTime::now()))
+            .for_update()
+            .first::<Invitation>(conn)
+            .optional()?
+            .ok_or(AuthError::InvalidInvitation)?;
+
+        // the record may have been linked by other means since the code was made
+        let (new_role, school) = match (invitation.student_id, invitation.teacher_id) {
+            (Some(student), _) => {
+                let student = update(
+                    students::table
+                        .find(student)
+                        .filter(students::user_id.is_null()),
+                )
+                .set(students::user_id.eq(user.id))
+                .get_result::<Student>(conn)
+                .optional()?
+                .ok_or(AuthError::InvalidInvitation)?;
+                (Role::Student, student.school_id)
+            }
+            (_, Some(teacher)) => {
+                let teacher = update(
+                    teachers::table
+                        .find(teacher)
+                        .filter(teachers::user_id.is_null()),
+                )
+                .set(teachers::user_id.eq(user.id))
+                .get_result::<Teacher>(conn)
+                .optional()?
+                .ok_or(AuthError::InvalidInvitation)?;
+                (Role::Teacher, teacher.school_id)
+            }
+            _ => return Err(AuthError::InvalidInvitation),
+        };
+
+        update(invitations::table.find(invitation.id))
+            .set((
+                invitations::redeemed_at.eq(SystemTime::now()),
+                invitations::redeemed_by.eq(user.id),
+            ))
+            .execute(conn)?;
+        println!("User {} redeemed invitation {}", user.login, invitation.id);
+
+        if user.school_id.is_some() || user.role == Role::SystemAdmin {
+            return Ok(users::table.find(user.id).first::<User>(conn)?);
+        }
+        Ok(update(users::table.find(user.id))
+            .set((users::role.eq(new_role), users::school_id.eq(school)))
+            .get_result::<User>(conn)?)
+    })
+}
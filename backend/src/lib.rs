@@ -1,11 +1,25 @@
+pub mod account;
 pub mod administration;
+pub mod api_tokens;
+pub mod audit;
 pub mod auth;
+pub mod config;
 pub mod database;
+pub mod errors;
+pub mod grading;
+pub mod guardians;
+pub mod invitations;
+pub mod lockout;
+pub mod mail;
 pub mod models;
+pub mod oidc;
 pub mod routes;
 pub mod schema;
+pub mod session_reaper;
+pub mod summaries;
+pub mod two_factor;
 
-use crate::database::get_connection_pool;
+use crate::{config::Config, database::get_connection_pool};
 use axum::{
     middleware::{self},
     response::Html,
@@ -17,13 +31,22 @@ use tower_http::trace::TraceLayer;
 
 pub fn app() -> Router {
     dotenv().ok();
+    let config = Config::from_env();
 
     Router::new()
         .route("/", get(handler))
         .layer(middleware::from_fn(routes::auth::middleware))
         .nest("/api/auth", routes::auth::router())
         .nest("/api/admin", routes::admin::router())
+        .nest("/api/guardian", routes::guardian::router())
+        .nest("/api/summary", routes::summaries::router())
+        .layer(middleware::from_fn(routes::csrf::middleware))
         .layer(Extension(get_connection_pool()))
+        .layer(Extension(
+            mail::from_config(&config.mail).expect("Failed to set up mailer"),
+        ))
+        .layer(Extension(config.cookies.key.clone()))
+        .layer(Extension(config))
         .layer(TraceLayer::new_for_http())
 }
 
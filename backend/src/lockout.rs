@@ -0,0 +1,132 @@
+use crate::{
+    audit,
+    auth::{AuthError, PgConn},
+    config::LockoutConfig,
+    models::NewAuditEntry,
+    schema::{login_failures, users},
+};
+use anyhow::Context;
+use diesel::{delete, dsl::count_star, insert_into, prelude::*};
+use std::{net::IpAddr, time::SystemTime};
+
+fn account_failures(
+    conn: &mut PgConnection,
+    user_login: &str,
+    config: &LockoutConfig,
+) -> anyhow::Result<i64> {
+    login_failures::table
+        .filter(login_failures::login.eq(user_login))
+        .filter(login_failures::created_at.gt(SystemTime::now() - config.failure_window))
+        .select(count_star())
+        .first(conn)
+        .context("Failed to count failed logins of account")
+}
+
+fn ip_failures(conn: &mut PgConnection, ip: IpAddr, config: &LockoutConfig) -> anyhow::Result<i64> {
+    login_failures::table
+        .filter(login_failures::ip.eq(ip.to_string()))
+        .filter(login_failures::created_at.gt(SystemTime::now() - config.failure_window))
+        .select(count_star())
+        .first(conn)
+        .context("Failed to count failed logins of address")
+}
+
+/// Refuses to check credentials for a locked account or address.
+/// Accounts are tracked by login, so unknown logins get locked the same way.
+pub fn check(
+    conn: &mut PgConn,
+    user_login: Option<&str>,
+    ip: IpAddr,
+    config: &LockoutConfig,
+) -> Result<(), AuthError> {
+    if ip_failures(conn, ip, config)? >= config.max_ip_failures {
+        println!("Address {ip} is locked out");
+        return Err(AuthError::TooManyAttempts);
+    }
+
+    if let Some(user_login) = user_login {
+        if account_failures(conn, user_login, config)? >= config.max_account_failures {
+            println!("Account {user_login} is locked out");
+            return Err(AuthError::TooManyAttempts);
+        }
+    }
+    Ok(())
+}
+
+/// Remembers a failed login and writes an audit entry when it locks the account or address
+pub fn record_failure(
+    conn: &mut PgConn,
+    user_login: Option<&str>,
+    ip: IpAddr,
+    config: &LockoutConfig,
+) -> anyhow::Result<()> {
+    conn.transaction(|conn| {
+        let ip_text = ip.to_string();
+
+        // failures outside the window don't count anymore
+        delete(
+            login_failures::table
+                .filter(login_failures::created_at.le(SystemTime::now() - config.failure_window))
+                .filter(
+                    login_failures::ip
+                        .eq(&ip_text)
+                        .or(login_failures::login.eq(user_login)),
+                ),
+        )
+        .execute(conn)
+        .context("Failed to delete old login failures")?;
+
+        insert_into(login_failures::table)
+            .values((
+                login_failures::login.eq(user_login),
+                login_failures::ip.eq(&ip_text),
+            ))
+            .execute(conn)
+            .context("Failed to record login failure")?;
+
+        if ip_failures(conn, ip, config)? == config.max_ip_failures {
+            audit::record(
+                conn,
+                NewAuditEntry {
+                    event: audit::IP_LOCKED,
+                    user_id: None,
+                    ip: Some(ip_text.clone()),
+                    details: format!("{} failed logins from {ip_text}", config.max_ip_failures),
+                },
+            )?;
+        }
+
+        let Some(user_login) = user_login else {
+            return Ok(());
+        };
+        if account_failures(conn, user_login, config)? == config.max_account_failures {
+            let user_uuid = users::table
+                .filter(users::login.eq(user_login))
+                .select(users::id)
+                .first(conn)
+                .optional()
+                .context("Failed to fetch locked user")?;
+            audit::record(
+                conn,
+                NewAuditEntry {
+                    event: audit::ACCOUNT_LOCKED,
+                    user_id: user_uuid,
+                    ip: Some(ip_text),
+                    details: format!(
+                        "{} failed logins of {user_login}",
+                        config.max_account_failures
+                    ),
+                },
+            )?;
+        }
+        Ok(())
+    })
+}
+
+/// Forgets failed logins of an account after a successful login
+pub fn clear(conn: &mut PgConn, user_login: &str) -> anyhow::Result<()> {
+    delete(login_failures::table.filter(login_failures::login.eq(user_login)))
+        .execute(conn)
+        .context("Failed to clear login failures")?;
+    Ok(())
+}
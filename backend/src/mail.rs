@@ -0,0 +1,93 @@
+use crate::config::{MailConfig, MailTransport};
+use anyhow::Context;
+use lettre::{message::Mailbox, SmtpTransport, Transport};
+use std::{fs, path::PathBuf, sync::Arc, time::SystemTime};
+use uuid::Uuid;
+
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Sends plain text mail, implemented by [`SmtpMailer`] and [`FileMailer`]
+pub trait Mailer: Send + Sync {
+    fn send(&self, mail: &Mail) -> anyhow::Result<()>;
+}
+
+pub type SharedMailer = Arc<dyn Mailer>;
+
+pub fn from_config(config: &MailConfig) -> anyhow::Result<SharedMailer> {
+    let mailer: SharedMailer = match &config.transport {
+        MailTransport::Smtp { url } => Arc::new(SmtpMailer::new(url, &config.from)?),
+        MailTransport::File { dir } => Arc::new(FileMailer::new(dir.clone(), &config.from)),
+    };
+    Ok(mailer)
+}
+
+pub struct SmtpMailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(url: &str, from: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            transport: SmtpTransport::from_url(url)
+                .context("Invalid SMTP url")?
+                .build(),
+            from: from.parse().context("Invalid sender address")?,
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, mail: &Mail) -> anyhow::Result<()> {
+        let message = lettre::Message::builder()
+            .from(self.from.clone())
+            .to(mail.to.parse().context("Invalid recipient address")?)
+            .subject(&mail.subject)
+            .body(mail.body.clone())
+            .context("Failed to build mail")?;
+
+        self.transport
+            .send(&message)
+            .context("Failed to send mail")?;
+        Ok(())
+    }
+}
+
+/// Outbox that stores every mail as a separate text file
+pub struct FileMailer {
+    dir: PathBuf,
+    from: String,
+}
+
+impl FileMailer {
+    pub fn new(dir: PathBuf, from: &str) -> Self {
+        Self {
+            dir,
+            from: from.to_string(),
+        }
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, mail: &Mail) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir).context("Failed to create outbox directory")?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .context("Invalid system time")?
+            .as_millis();
+        let path = self.dir.join(format!("{timestamp}-{}.eml", Uuid::new_v4()));
+        let content = format!(
+            "From: {}\nTo: {}\nSubject: {}\n\n{}\n",
+            self.from, mail.to, mail.subject, mail.body
+        );
+
+        fs::write(&path, content).context("Failed to write mail to outbox")?;
+        println!("Mail to {} saved in {}", mail.to, path.display());
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
-use backend::app;
+use backend::{app, config::Config, database::get_connection_pool, session_reaper};
 use std::net::SocketAddr;
+use tokio::sync::watch;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -11,10 +12,48 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    dotenv::dotenv().ok();
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    let reaper = session_reaper::spawn(
+        get_connection_pool(),
+        Config::from_env().session,
+        shutdown_rx,
+    );
+
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
     println!("listening on {}", addr);
     axum::Server::bind(&addr)
-        .serve(app().into_make_service())
+        .serve(app().into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .expect("Failed to run axum server");
+
+    shutdown_tx.send(()).ok();
+    reaper.await.expect("Session reaper panicked");
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for ctrl+c");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    println!("shutting down");
 }
@@ -1,12 +1,66 @@
 use crate::schema::{
-    class_students, classes, grades, groups, schools, sessions, students, subjects, tasks,
-    teachers, users,
+    api_tokens, audit_log, class_students, classes, grade_symbols, grades, groups, guardians,
+    invitations, oidc_identities, recovery_codes, schools, sessions, students, subjects, tasks,
+    teachers, user_tokens, users,
 };
-use diesel::prelude::*;
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types::Text,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{io::Write, str::FromStr};
 use time::Date;
 use uuid::Uuid;
 
-#[derive(Queryable, Identifiable)]
+/// Personal token for scripts, sent as `Authorization: Bearer`. Only its hash is stored.
+#[derive(Queryable, Serialize, Debug, Clone)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: std::time::SystemTime,
+    pub expires_at: Option<std::time::SystemTime>,
+    pub last_used_at: Option<std::time::SystemTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = api_tokens)]
+pub struct NewApiToken<'a> {
+    pub user_id: Uuid,
+    pub name: &'a str,
+    pub token_hash: &'a str,
+    pub scopes: &'a [Scope],
+    pub expires_at: Option<std::time::SystemTime>,
+}
+
+/// Security relevant event, e.g. an account lockout
+#[derive(Queryable, Serialize, Debug)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub event: String,
+    pub user_id: Option<Uuid>,
+    pub ip: Option<String>,
+    pub details: String,
+    pub created_at: std::time::SystemTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = audit_log)]
+pub struct NewAuditEntry<'a> {
+    pub event: &'a str,
+    pub user_id: Option<Uuid>,
+    pub ip: Option<String>,
+    pub details: String,
+}
+
+#[derive(Queryable, Identifiable, Serialize)]
 #[diesel(primary_key(class_id, student_id))]
 pub struct ClassStudent {
     pub class_id: Uuid,
@@ -20,7 +74,7 @@ pub struct NewClassStudent {
     pub student_id: Uuid,
 }
 
-#[derive(Queryable)]
+#[derive(Queryable, Serialize)]
 pub struct Class {
     pub id: Uuid,
     pub subject_id: Uuid,
@@ -36,29 +90,97 @@ pub struct NewClass {
     pub teacher_id: Uuid,
 }
 
-#[derive(Queryable, Identifiable)]
-#[diesel(primary_key(student_id, subject_id, task_id))]
+#[derive(AsChangeset, Deserialize)]
+#[diesel(table_name = classes)]
+pub struct UpdateClass {
+    pub subject_id: Option<Uuid>,
+    pub group_id: Option<Uuid>,
+    pub teacher_id: Option<Uuid>,
+}
+
+#[derive(Queryable, Identifiable, Serialize)]
 pub struct Grade {
-    pub value: f64,
+    /// Numeric equivalent of the symbol, `None` for marks like "np"
+    pub value: Option<f64>,
     pub weight: i32,
     pub task_id: Uuid,
     pub student_id: Uuid,
     pub subject_id: Uuid,
     pub teacher_id: Uuid,
+    /// Grade as written in the register, e.g. "4+"
+    pub symbol: String,
+    /// Whether the grade is part of averages
+    pub counts: bool,
+    pub id: Uuid,
+    /// 1 for the first try at the task, retakes and corrections count up from there
+    pub attempt: i32,
+    /// Earlier attempt this grade corrects
+    pub corrects_id: Option<Uuid>,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = grades)]
-pub struct NewGrade {
-    pub value: f64,
+pub struct NewGrade<'a> {
+    pub value: Option<f64>,
     pub weight: i32,
     pub task_id: Uuid,
     pub student_id: Uuid,
     pub subject_id: Uuid,
     pub teacher_id: Uuid,
+    pub symbol: &'a str,
+    pub counts: bool,
+    pub attempt: i32,
+    pub corrects_id: Option<Uuid>,
+}
+
+/// Entry of a grade's history, written by the database on every change. `old_*`
+/// fields are `None` when the grade was given, `new_*` ones when it was deleted.
+#[derive(Queryable, Serialize, Debug)]
+pub struct GradeHistoryEntry {
+    pub id: Uuid,
+    pub grade_id: Uuid,
+    pub student_id: Uuid,
+    pub old_symbol: Option<String>,
+    pub new_symbol: Option<String>,
+    pub old_value: Option<f64>,
+    pub new_value: Option<f64>,
+    pub old_weight: Option<i32>,
+    pub new_weight: Option<i32>,
+    /// Teacher of the grade after the change, before it for deletions
+    pub teacher_id: Uuid,
+    /// User who made the change, `None` for changes made outside the application
+    pub changed_by: Option<Uuid>,
+    pub reason: Option<String>,
+    pub changed_at: std::time::SystemTime,
+}
+
+/// Value and counting of a grade follow from its symbol, only the symbol is sent
+#[derive(AsChangeset, Deserialize, Clone)]
+#[diesel(table_name = grades)]
+pub struct UpdateGrade {
+    pub symbol: Option<String>,
+    #[serde(skip)]
+    pub value: Option<Option<f64>>,
+    #[serde(skip)]
+    pub counts: Option<bool>,
+    pub weight: Option<i32>,
+    pub teacher_id: Option<Uuid>,
+}
+
+/// Symbol of a school's grading scale
+#[derive(Queryable, Insertable, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[diesel(table_name = grade_symbols)]
+pub struct GradeSymbol {
+    #[serde(skip)]
+    pub school_id: Uuid,
+    pub symbol: String,
+    /// Numeric equivalent, `None` for marks which aren't numbers
+    pub value: Option<f64>,
+    /// Whether grades with this symbol are part of averages
+    pub counts: bool,
 }
 
-#[derive(Queryable)]
+#[derive(Queryable, Serialize)]
 pub struct Group {
     pub id: Uuid,
     pub name: String,
@@ -72,12 +194,52 @@ pub struct NewGroup<'a> {
     pub school_id: Uuid,
 }
 
-#[derive(Queryable)]
+#[derive(AsChangeset, Deserialize)]
+#[diesel(table_name = groups)]
+pub struct UpdateGroup {
+    pub name: Option<String>,
+}
+
+/// Parent or other adult who may follow a student's progress
+#[derive(Queryable, Identifiable, Serialize, Debug)]
+#[diesel(primary_key(user_id, student_id))]
+pub struct Guardian {
+    pub user_id: Uuid,
+    pub student_id: Uuid,
+    pub relationship: Relationship,
+    /// Contacted first about the student
+    pub primary_contact: bool,
+    /// May be called in an emergency
+    pub emergency_contact: bool,
+    pub created_at: std::time::SystemTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = guardians)]
+pub struct NewGuardian {
+    pub user_id: Uuid,
+    pub student_id: Uuid,
+    pub relationship: Relationship,
+    pub primary_contact: bool,
+    pub emergency_contact: bool,
+}
+
+#[derive(AsChangeset, Deserialize)]
+#[diesel(table_name = guardians)]
+pub struct UpdateGuardian {
+    pub relationship: Option<Relationship>,
+    pub primary_contact: Option<bool>,
+    pub emergency_contact: Option<bool>,
+}
+
+#[derive(Queryable, Serialize)]
 pub struct School {
     pub id: Uuid,
     pub name: String,
     pub place: String,
     pub school_type: Option<String>,
+    /// Which attempt at a task counts towards averages
+    pub retake_policy: RetakePolicy,
 }
 
 #[derive(Insertable)]
@@ -88,11 +250,25 @@ pub struct NewSchool<'a> {
     pub school_type: Option<&'a str>,
 }
 
-#[derive(Queryable)]
+#[derive(AsChangeset, Deserialize)]
+#[diesel(table_name = schools)]
+pub struct UpdateSchool {
+    pub name: Option<String>,
+    pub place: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub school_type: Option<Option<String>>,
+    pub retake_policy: Option<RetakePolicy>,
+}
+
+#[derive(Queryable, Serialize, Debug, Clone)]
 pub struct Session {
     pub id: Uuid,
     pub iat: std::time::SystemTime,
     pub user_id: Uuid,
+    pub last_seen: std::time::SystemTime,
+    pub remember: bool,
+    /// System admin acting as the user, see [`start_impersonation`](crate::auth::start_impersonation)
+    pub impersonator_id: Option<Uuid>,
 }
 
 #[derive(Insertable)]
@@ -101,7 +277,7 @@ pub struct NewSession {
     pub user_id: Uuid,
 }
 
-#[derive(Queryable)]
+#[derive(Queryable, Serialize)]
 pub struct Student {
     pub id: Uuid,
     pub first_name: String,
@@ -123,7 +299,18 @@ pub struct NewStudent<'a> {
     pub school_id: Uuid,
 }
 
-#[derive(Queryable)]
+#[derive(AsChangeset, Deserialize)]
+#[diesel(table_name = students)]
+pub struct UpdateStudent {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub date_of_birth: Option<Date>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub user_id: Option<Option<Uuid>>,
+    pub group_id: Option<Uuid>,
+}
+
+#[derive(Queryable, Serialize)]
 pub struct Subject {
     pub id: Uuid,
     pub name: String,
@@ -137,24 +324,61 @@ pub struct NewSubject<'a> {
     pub school_id: Uuid,
 }
 
-#[derive(Queryable)]
+#[derive(AsChangeset, Deserialize)]
+#[diesel(table_name = subjects)]
+pub struct UpdateSubject {
+    pub name: Option<String>,
+}
+
+/// Test, homework or other work of a class which is graded
+#[derive(Queryable, Serialize)]
 pub struct Task {
     pub id: Uuid,
     pub name: String,
+    /// `None` for tasks from before tasks belonged to classes
+    pub class_id: Option<Uuid>,
+    pub category: TaskCategory,
+    /// Weight of the task's grades, unless a grade sets its own
+    pub weight: i32,
+    /// `None` for tasks from before tasks belonged to classes
+    pub date: Option<Date>,
+    pub description: Option<String>,
+    pub max_points: Option<f64>,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = tasks)]
 pub struct NewTask<'a> {
     pub name: &'a str,
+    pub class_id: Uuid,
+    pub category: TaskCategory,
+    pub weight: i32,
+    pub date: Date,
+    pub description: Option<&'a str>,
+    pub max_points: Option<f64>,
 }
 
-#[derive(Queryable)]
+/// Grades given earlier keep their weight when the task's weight changes
+#[derive(AsChangeset, Deserialize)]
+#[diesel(table_name = tasks)]
+pub struct UpdateTask {
+    pub name: Option<String>,
+    pub class_id: Option<Uuid>,
+    pub category: Option<TaskCategory>,
+    pub weight: Option<i32>,
+    pub date: Option<Date>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub max_points: Option<Option<f64>>,
+}
+
+#[derive(Queryable, Serialize)]
 pub struct Teacher {
     pub id: Uuid,
     pub first_name: String,
     pub last_name: String,
-    pub user_id: Uuid,
+    pub user_id: Option<Uuid>,
     pub school_id: Uuid,
 }
 
@@ -163,16 +387,42 @@ pub struct Teacher {
 pub struct NewTeacher<'a> {
     pub first_name: &'a str,
     pub last_name: &'a str,
-    pub user_id: Uuid,
+    pub user_id: Option<Uuid>,
     pub school_id: Uuid,
 }
 
-#[derive(Queryable, Debug, PartialEq, Eq, Clone)]
+#[derive(AsChangeset, Deserialize)]
+#[diesel(table_name = teachers)]
+pub struct UpdateTeacher {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub user_id: Option<Option<Uuid>>,
+}
+
+#[derive(Queryable, Serialize, Debug, PartialEq, Eq, Clone)]
 pub struct User {
     pub id: Uuid,
     pub login: String,
     pub email: String,
+    #[serde(skip_serializing)]
     pub password: String,
+    pub role: Role,
+    pub school_id: Option<Uuid>,
+    /// When the user confirmed their email, `None` until then
+    pub verified_at: Option<std::time::SystemTime>,
+    /// Base32 TOTP secret, set when enrollment starts
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    /// When two-factor authentication was enabled, `None` while it's off
+    pub totp_enabled_at: Option<std::time::SystemTime>,
+    /// New address waiting for confirmation, `email` stays in use until then
+    pub pending_email: Option<String>,
+    /// When the user deleted their account, its personal data is gone since
+    pub deleted_at: Option<std::time::SystemTime>,
+    /// 30 second step of the last accepted TOTP code, codes can't be used twice
+    #[serde(skip_serializing)]
+    pub totp_last_step: Option<i64>,
 }
 
 #[derive(Insertable)]
@@ -182,3 +432,218 @@ pub struct NewUser<'a> {
     pub email: &'a str,
     pub password: &'a str,
 }
+
+/// Single-use token mailed to a user, only its hash is stored
+#[derive(Queryable, Debug)]
+pub struct UserToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub purpose: String,
+    pub token_hash: String,
+    pub created_at: std::time::SystemTime,
+    pub expires_at: std::time::SystemTime,
+    pub used_at: Option<std::time::SystemTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = user_tokens)]
+pub struct NewUserToken<'a> {
+    pub user_id: Uuid,
+    pub purpose: &'a str,
+    pub token_hash: &'a str,
+    pub expires_at: std::time::SystemTime,
+}
+
+/// One-time code which attaches the account of whoever redeems it to a student or teacher
+/// record. Only its hash is stored.
+#[derive(Queryable, Serialize, Debug)]
+pub struct Invitation {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub code_hash: String,
+    pub student_id: Option<Uuid>,
+    pub teacher_id: Option<Uuid>,
+    pub created_by: Uuid,
+    pub created_at: std::time::SystemTime,
+    pub expires_at: std::time::SystemTime,
+    pub redeemed_at: Option<std::time::SystemTime>,
+    pub redeemed_by: Option<Uuid>,
+    pub revoked_at: Option<std::time::SystemTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = invitations)]
+pub struct NewInvitation<'a> {
+    pub code_hash: &'a str,
+    pub student_id: Option<Uuid>,
+    pub teacher_id: Option<Uuid>,
+    pub created_by: Uuid,
+    pub expires_at: std::time::SystemTime,
+}
+
+/// Account at an OpenID Connect provider linked to a user
+#[derive(Queryable, Debug)]
+pub struct OidcIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub user_id: Uuid,
+    pub created_at: std::time::SystemTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = oidc_identities)]
+pub struct NewOidcIdentity<'a> {
+    pub issuer: &'a str,
+    pub subject: &'a str,
+    pub user_id: Uuid,
+}
+
+/// Login which passed the password check and waits for a second factor
+#[derive(Queryable, Debug)]
+pub struct PendingLogin {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub remember: bool,
+    pub created_at: std::time::SystemTime,
+    /// Hash of the invitation code sent with the password, redeemed after the second factor
+    pub invitation_hash: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = recovery_codes)]
+pub struct NewRecoveryCode {
+    pub user_id: Uuid,
+    pub code_hash: String,
+}
+
+/// Declares an enum stored as text, with `as_str`, `FromStr` and the diesel conversions.
+/// Each variant names its text, which is also its JSON form.
+macro_rules! text_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident ($what:literal) {
+            $($(#[$variant_meta:meta])* $variant:ident => $text:literal,)+
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(
+            Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Serialize, Deserialize,
+        )]
+        #[diesel(sql_type = Text)]
+        pub enum $name {
+            $(
+                $(#[$variant_meta])*
+                #[serde(rename = $text)]
+                $variant,
+            )+
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $text,)+
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($text => Ok($name::$variant),)+
+                    _ => Err(format!(concat!("Unknown ", $what, ": {}"), s)),
+                }
+            }
+        }
+
+        impl ToSql<Text, Pg> for $name {
+            fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+                out.write_all(self.as_str().as_bytes())?;
+                Ok(IsNull::No)
+            }
+        }
+
+        impl FromSql<Text, Pg> for $name {
+            fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+                Ok(std::str::from_utf8(bytes.as_bytes())?.parse()?)
+            }
+        }
+    };
+}
+
+text_enum! {
+    /// Role of a user, stored as text in `users.role`
+    pub enum Role("role") {
+        SystemAdmin => "system_admin",
+        SchoolAdmin => "school_admin",
+        Teacher => "teacher",
+        Student => "student",
+        Parent => "parent",
+    }
+}
+
+text_enum! {
+    /// How a [`Guardian`] is related to the student, stored as text in `guardians.relationship`
+    pub enum Relationship("relationship") {
+        Mother => "mother",
+        Father => "father",
+        LegalGuardian => "legal_guardian",
+        Other => "other",
+    }
+}
+
+text_enum! {
+    /// Which of several attempts at a task counts towards averages,
+    /// stored as text in `schools.retake_policy`
+    pub enum RetakePolicy("retake policy") {
+        /// The last attempt replaces the earlier ones
+        Latest => "latest",
+        /// The attempt with the highest value
+        Best => "best",
+        /// Mean of all attempts, weighted like the last one
+        Average => "average",
+    }
+}
+
+text_enum! {
+    /// Kind of a task, stored as text in `tasks.category`
+    pub enum TaskCategory("task category") {
+        Test => "test",
+        Quiz => "quiz",
+        Homework => "homework",
+        /// Work in class, e.g. answering at the blackboard
+        Activity => "activity",
+    }
+}
+
+impl TaskCategory {
+    /// Weight of new tasks of the category which don't set their own
+    pub fn default_weight(&self) -> i32 {
+        match self {
+            TaskCategory::Test => 3,
+            TaskCategory::Quiz => 2,
+            TaskCategory::Homework | TaskCategory::Activity => 1,
+        }
+    }
+}
+
+text_enum! {
+    /// What an [`ApiToken`] may do, stored as text in `api_tokens.scopes`
+    pub enum Scope("scope") {
+        /// Safe requests, e.g. GET
+        Read => "read",
+        /// Requests which change data, e.g. POST or DELETE
+        Write => "write",
+    }
+}
+
+/// Tells apart a missing field (`None`) from an explicit `null` (`Some(None)`)
+/// so that nullable columns can be cleared with a PATCH
+fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Deserialize::deserialize(deserializer).map(Some)
+}
@@ -0,0 +1,340 @@
+use crate::{
+    audit,
+    auth::{hash_pass, random_token, AuthError, PgConn},
+    config::{OidcConfig, PasswordConfig},
+    models::{NewAuditEntry, NewOidcIdentity, NewUser, User},
+    schema::{oidc_identities, users},
+};
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use diesel::{insert_into, prelude::*, update};
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{AlgorithmParameters, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use rand::{thread_rng, Rng};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Endpoints of the identity provider from its discovery document
+#[derive(Deserialize, Debug)]
+pub struct Metadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+fn http_client() -> anyhow::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .context("Failed to build HTTP client")
+}
+
+pub async fn discover(config: &OidcConfig) -> anyhow::Result<Metadata> {
+    let url = format!("{}/.well-known/openid-configuration", config.issuer);
+    let metadata: Metadata = http_client()?
+        .get(&url)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .with_context(|| format!("Failed to fetch {url}"))?
+        .json()
+        .await
+        .context("Invalid discovery document")?;
+
+    if metadata.issuer.trim_end_matches('/') != config.issuer {
+        anyhow::bail!("Discovery document is for issuer {}", metadata.issuer);
+    }
+    Ok(metadata)
+}
+
+/// Login waiting for the provider to send the user back, kept in a signed cookie
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginRequest {
+    pub state: String,
+    pub nonce: String,
+    /// PKCE secret, the provider only gets its hash until the code is exchanged
+    pub code_verifier: String,
+    pub remember: bool,
+    /// Unix timestamp in seconds
+    expires_at: u64,
+}
+
+impl LoginRequest {
+    pub fn new(remember: bool, config: &OidcConfig) -> Self {
+        let expires_at = SystemTime::now() + config.login_timeout;
+        Self {
+            state: random_token(),
+            nonce: random_token(),
+            code_verifier: random_token(),
+            remember,
+            expires_at: expires_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("Login request is always serializable");
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    /// Reads a request written by [`LoginRequest::encode`], `None` when it's malformed or expired
+    pub fn decode(value: &str) -> Option<Self> {
+        let json = URL_SAFE_NO_PAD.decode(value).ok()?;
+        let request: Self = serde_json::from_slice(&json).ok()?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        (request.expires_at > now).then_some(request)
+    }
+}
+
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Address of the provider's login page the user is sent to
+pub fn authorization_url(
+    metadata: &Metadata,
+    config: &OidcConfig,
+    request: &LoginRequest,
+) -> anyhow::Result<String> {
+    let url = Url::parse_with_params(
+        &metadata.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", &config.client_id),
+            ("redirect_uri", &config.redirect_url),
+            ("scope", "openid email profile"),
+            ("state", &request.state),
+            ("nonce", &request.nonce),
+            ("code_challenge", &code_challenge(&request.code_verifier)),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .context("Invalid authorization endpoint")?;
+    Ok(url.into())
+}
+
+/// Claims of a verified ID token
+#[derive(Deserialize, Debug)]
+pub struct Claims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    nonce: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// Exchanges the authorization code for an ID token and verifies it
+/// against the provider's published keys
+pub async fn exchange_code(
+    metadata: &Metadata,
+    config: &OidcConfig,
+    code: &str,
+    request: &LoginRequest,
+) -> Result<Claims, AuthError> {
+    let client = http_client()?;
+
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &config.redirect_url),
+        ("client_id", &config.client_id),
+        ("code_verifier", &request.code_verifier),
+    ];
+    if let Some(secret) = &config.client_secret {
+        form.push(("client_secret", secret));
+    }
+    let res = client
+        .post(&metadata.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .context("Failed to reach the token endpoint")?;
+    if !res.status().is_success() {
+        println!("Token endpoint refused the code: {}", res.status());
+        return Err(AuthError::ExternalLoginFailed);
+    }
+    let tokens: TokenResponse = res.json().await.context("Invalid token response")?;
+
+    let header = decode_header(&tokens.id_token).map_err(|_| AuthError::ExternalLoginFailed)?;
+    // the client secret isn't used as a key, only the provider's public keys are trusted
+    if !matches!(
+        header.alg,
+        Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512
+            | Algorithm::ES256
+            | Algorithm::ES384
+    ) {
+        println!("ID token is signed with {:?}", header.alg);
+        return Err(AuthError::ExternalLoginFailed);
+    }
+
+    let jwks: JwkSet = client
+        .get(&metadata.jwks_uri)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .context("Failed to fetch the provider's keys")?
+        .json()
+        .await
+        .context("Invalid JWKS document")?;
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None => jwks.keys.first(),
+    }
+    .filter(|jwk| !matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)))
+    .ok_or(AuthError::ExternalLoginFailed)?;
+    let key = DecodingKey::from_jwk(jwk).map_err(|_| AuthError::ExternalLoginFailed)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[&config.client_id]);
+    validation.set_issuer(&[&metadata.issuer]);
+    let claims = decode::<Claims>(&tokens.id_token, &key, &validation)
+        .map_err(|e| {
+            println!("Rejected ID token: {e}");
+            AuthError::ExternalLoginFailed
+        })?
+        .claims;
+
+    if claims.nonce.as_deref() != Some(request.nonce.as_str()) {
+        println!("ID token nonce doesn't match");
+        return Err(AuthError::ExternalLoginFailed);
+    }
+    Ok(claims)
+}
+
+/// Finds the user linked to the provider's subject. Otherwise links the user with the
+/// same verified email or, if `provision` is set, creates a new account.
+pub fn find_or_create_user(
+    conn: &mut PgConn,
+    issuer: &str,
+    claims: &Claims,
+    provision: bool,
+    password_config: &PasswordConfig,
+) -> Result<User, AuthError> {
+    conn.transaction(|conn| {
+        let linked = oidc_identities::table
+            .inner_join(users::table)
+            .filter(oidc_identities::issuer.eq(issuer))
+            .filter(oidc_identities::subject.eq(&claims.sub))
+            .select(users::all_columns)
+            .first::<User>(conn)
+            .optional()?;
+        if let Some(user) = linked {
+            return Ok(user);
+        }
+
+        // an address the provider didn't verify could belong to anyone
+        let email = match &claims.email {
+            Some(email) if claims.email_verified => email,
+            _ => {
+                println!("Provider didn't send a verified email for {}", claims.sub);
+                return Err(AuthError::ExternalLoginFailed);
+            }
+        };
+
+        let existing = users::table
+            .filter(users::email.eq(email))
+            .first::<User>(conn)
+            .optional()?;
+        let (user, event) = match existing {
+            Some(user) => (user, audit::OIDC_LINKED),
+            None if provision => (
+                create_user(conn, email, claims, password_config)?,
+                audit::OIDC_PROVISIONED,
+            ),
+            None => {
+                println!(
+                    "No account to link {} to and provisioning is off",
+                    claims.sub
+                );
+                return Err(AuthError::ExternalLoginFailed);
+            }
+        };
+
+        // the provider vouches for the address
+        let user = if user.verified_at.is_none() {
+            update(users::table.find(user.id))
+                .set(users::verified_at.eq(SystemTime::now()))
+                .get_result::<User>(conn)?
+        } else {
+            user
+        };
+
+        insert_into(oidc_identities::table)
+            .values(&NewOidcIdentity {
+                issuer,
+                subject: &claims.sub,
+                user_id: user.id,
+            })
+            .execute(conn)?;
+        audit::record(
+            conn,
+            NewAuditEntry {
+                event,
+                user_id: Some(user.id),
+                ip: None,
+                details: format!("Linked {} subject {}", issuer, claims.sub),
+            },
+        )?;
+        Ok(user)
+    })
+}
+
+/// Creates an account without a usable password, one can be set with a password reset
+fn create_user(
+    conn: &mut PgConnection,
+    email: &str,
+    claims: &Claims,
+    password_config: &PasswordConfig,
+) -> Result<User, AuthError> {
+    let base: String = claims
+        .preferred_username
+        .as_deref()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default())
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+        .collect();
+    let base = if base.is_empty() { "user" } else { &base };
+
+    let mut login = base.to_string();
+    while users::table
+        .filter(users::login.eq(&login))
+        .first::<User>(conn)
+        .optional()?
+        .is_some()
+    {
+        login = format!("{base}_{:04}", thread_rng().gen_range(0..10000));
+    }
+
+    let user = insert_into(users::table)
+        .values(&NewUser {
+            login: &login,
+            email,
+            password: &hash_pass(&random_token(), password_config)?,
+        })
+        .get_result::<User>(conn)
+        .context("Failed to insert user")?;
+    println!("Provisioned user {} for {}", user.id, claims.sub);
+    Ok(user)
+}
@@ -0,0 +1,126 @@
+use super::{
+    auth::{removal_cookie, send_mail, track_failure},
+    extract, ClientIp,
+};
+use crate::{
+    account, administration,
+    config::Config,
+    database::PgPool,
+    errors::ApiError,
+    lockout,
+    mail::{Mail, SharedMailer},
+    models::User,
+};
+use axum::{http::StatusCode, response::Html, Extension, Json};
+use axum_extra::extract::SignedCookieJar;
+use serde::Deserialize;
+
+pub async fn get_profile(Extension(user): Extension<User>) -> Json<User> {
+    Json(user)
+}
+
+#[derive(Deserialize)]
+pub struct UpdateProfile {
+    /// Current password
+    password: String,
+    login: Option<String>,
+    /// Takes effect once confirmed through the link mailed to the new address
+    email: Option<String>,
+}
+
+pub async fn patch_profile(
+    extract::Json(payload): extract::Json<UpdateProfile>,
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+    mailer: Extension<SharedMailer>,
+    Extension(user): Extension<User>,
+    ClientIp(ip): ClientIp,
+) -> Result<Json<User>, ApiError> {
+    let mut conn = pool.get()?;
+    let new_email = payload.email.filter(|new_email| *new_email != user.email);
+    if payload.login.is_none() && new_email.is_none() {
+        return Err(administration::Error::NothingToUpdate.into());
+    }
+    lockout::check(&mut conn, Some(&user.login), ip, &config.lockout)?;
+    let result = account::check_password(&user, &payload.password);
+    track_failure(&mut conn, Some(&user.login), ip, &config, result)?;
+
+    let mut user = match &payload.login {
+        Some(new_login) => account::change_login(&mut conn, &user, new_login)?,
+        None => user,
+    };
+
+    if let Some(new_email) = new_email {
+        let lifetime = config.tokens.email_verification;
+        let token = account::request_email_change(&mut conn, &user, &new_email, lifetime)?;
+        let confirmation = Mail {
+            to: new_email.clone(),
+            subject: "Confirm your new Bibrus email address".to_string(),
+            body: format!(
+                "Hello {},\n\nconfirm your new email address using this link:\n\
+                {}/confirm-email?token={}\n\n\
+                The link expires in {} hours. If you didn't ask for it, ignore this message.",
+                user.login,
+                config.app_url,
+                token,
+                lifetime.whole_hours()
+            ),
+        };
+        send_mail(&mailer, confirmation).await?;
+
+        // warns the owner in case someone else got hold of the account
+        let notice = Mail {
+            to: user.email.clone(),
+            subject: "Bibrus email change requested".to_string(),
+            body: format!(
+                "Hello {},\n\nsomeone asked to change the email address of your account to {}.\n\
+                It changes once the new address is confirmed. If it wasn't you, reset your \
+                password at {}/forgot-pass right away.",
+                user.login, new_email, config.app_url
+            ),
+        };
+        send_mail(&mailer, notice).await?;
+        user.pending_email = Some(new_email);
+    }
+
+    Ok(Json(user))
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmEmail {
+    token: String,
+}
+
+pub async fn post_confirm_email(
+    extract::Json(payload): extract::Json<ConfirmEmail>,
+    pool: Extension<PgPool>,
+) -> Result<Html<&'static str>, ApiError> {
+    let mut conn = pool.get()?;
+
+    account::confirm_email_change(&mut conn, &payload.token)?;
+    Ok(Html("<h1>Email changed</h1>"))
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccount {
+    /// Current password
+    password: String,
+}
+
+/// Anonymises the account and logs the user out everywhere
+pub async fn delete_profile(
+    extract::Json(payload): extract::Json<DeleteAccount>,
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+    Extension(user): Extension<User>,
+    ClientIp(ip): ClientIp,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, StatusCode), ApiError> {
+    let mut conn = pool.get()?;
+    lockout::check(&mut conn, Some(&user.login), ip, &config.lockout)?;
+    let result = account::check_password(&user, &payload.password);
+    track_failure(&mut conn, Some(&user.login), ip, &config, result)?;
+
+    account::delete_account(&mut conn, &user, ip, &config.password)?;
+    Ok((jar.remove(removal_cookie()), StatusCode::NO_CONTENT))
+}
@@ -1,20 +1,134 @@
-use crate::{administration, database::PgPool};
-use axum::{extract, http::StatusCode, response::Html, routing::post, Extension, Router};
-use serde::Deserialize;
+use super::{auth::session_cookie, extract, ClientIp};
+use crate::{
+    administration,
+    auth::{self, authorize, AuthError},
+    config::Config,
+    database::PgPool,
+    errors::ApiError,
+    grading, guardians,
+    invitations::{self, Target},
+    models::{
+        Class, ClassStudent, Grade, GradeHistoryEntry, GradeSymbol, Group, Guardian, Invitation,
+        NewGuardian, NewTask, Relationship, Role, School, Session, Student, Subject, Task,
+        TaskCategory, Teacher, UpdateClass, UpdateGrade, UpdateGroup, UpdateGuardian, UpdateSchool,
+        UpdateStudent, UpdateSubject, UpdateTask, UpdateTeacher, User,
+    },
+};
+use axum::{
+    http::{header, HeaderName, StatusCode},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use axum_extra::extract::SignedCookieJar;
+use serde::{Deserialize, Serialize};
 use time::Date;
 use uuid::Uuid;
 
 pub fn router() -> Router {
     Router::new()
-        .route("/school", post(post_create_school))
-        .route("/student", post(post_create_student))
-        .route("/teacher", post(post_create_teacher))
-        .route("/subject", post(post_create_subject))
-        .route("/group", post(post_create_group))
-        .route("/class", post(post_create_class))
-        .route("/class-student", post(post_create_class_student))
-        .route("/grade", post(post_create_grade))
-        .route("/task", post(post_create_task))
+        .route("/school", get(get_schools).post(post_create_school))
+        .route(
+            "/school/:id",
+            get(get_school).patch(patch_school).delete(delete_school),
+        )
+        .route(
+            "/school/:id/grading-scale",
+            get(get_grading_scale).put(put_grading_scale),
+        )
+        .route("/student", get(get_students).post(post_create_student))
+        .route(
+            "/student/:id",
+            get(get_student).patch(patch_student).delete(delete_student),
+        )
+        .route("/teacher", get(get_teachers).post(post_create_teacher))
+        .route(
+            "/teacher/:id",
+            get(get_teacher).patch(patch_teacher).delete(delete_teacher),
+        )
+        .route("/subject", get(get_subjects).post(post_create_subject))
+        .route(
+            "/subject/:id",
+            get(get_subject).patch(patch_subject).delete(delete_subject),
+        )
+        .route("/group", get(get_groups).post(post_create_group))
+        .route(
+            "/group/:id",
+            get(get_group).patch(patch_group).delete(delete_group),
+        )
+        .route("/class", get(get_classes).post(post_create_class))
+        .route(
+            "/class/:id",
+            get(get_class).patch(patch_class).delete(delete_class),
+        )
+        .route(
+            "/class-student",
+            get(get_class_students).post(post_create_class_student),
+        )
+        .route(
+            "/class-student/:class_id/:student_id",
+            get(get_class_student).delete(delete_class_student),
+        )
+        .route("/guardian", get(get_guardians).post(post_create_guardian))
+        .route(
+            "/guardian/:user_id/:student_id",
+            get(get_guardian)
+                .patch(patch_guardian)
+                .delete(delete_guardian),
+        )
+        .route("/grade", get(get_grades).post(post_create_grade))
+        .route(
+            "/grade/:id",
+            get(get_grade).patch(patch_grade).delete(delete_grade),
+        )
+        .route("/grade/:id/history", get(get_grade_history))
+        .route("/task", get(get_tasks).post(post_create_task))
+        .route(
+            "/task/:id",
+            get(get_task).patch(patch_task).delete(delete_task),
+        )
+        .route(
+            "/invitation",
+            get(get_invitations).post(post_create_invitation),
+        )
+        .route(
+            "/invitation/:id",
+            get(get_invitation).delete(delete_invitation),
+        )
+        .route("/role", post(post_assign_role))
+        .route("/impersonate", post(post_impersonate))
+        .route_layer(axum::middleware::from_fn(super::auth::two_factor_policy))
+        .route_layer(axum::middleware::from_fn(super::auth::middleware))
+}
+
+type Created<T> = (StatusCode, [(HeaderName, String); 1], Json<T>);
+
+/// Answers with `201 Created`, pointing `Location` at the new entity
+fn created<T: Serialize>(location: String, entity: T) -> Created<T> {
+    (
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        Json(entity),
+    )
+}
+
+/// Roles allowed to read school data
+const STAFF: &[Role] = &[Role::SchoolAdmin, Role::Teacher];
+
+/// Limits listings to the user's own school, system admins see everything
+fn school_filter(user: &User) -> Result<Option<Uuid>, ApiError> {
+    authorize(user, STAFF, None)?;
+    if user.role == Role::SystemAdmin {
+        return Ok(None);
+    }
+    user.school_id.map(Some).ok_or_else(ApiError::forbidden)
+}
+
+/// Tasks from before tasks belonged to classes have no school, only system admins reach them
+fn authorize_task(user: &User, school: Option<Uuid>) -> Result<(), AuthError> {
+    match school {
+        Some(school) => authorize(user, STAFF, Some(school)),
+        None => authorize(user, &[], None),
+    }
 }
 
 #[derive(Deserialize)]
@@ -27,20 +141,19 @@ struct CreateSchool {
 async fn post_create_school(
     extract::Json(payload): extract::Json<CreateSchool>,
     pool: Extension<PgPool>,
-) -> Result<Html<&'static str>, StatusCode> {
-    let mut conn = pool.get().map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Extension(user): Extension<User>,
+) -> Result<Created<School>, ApiError> {
+    let mut conn = pool.get()?;
+    authorize(&user, &[], None)?;
 
     let school = administration::create_school(
         &mut conn,
         &payload.name,
         &payload.place,
         payload.school_type.as_deref(),
-    );
+    )?;
 
-    match school {
-        Ok(_) => Ok(Html("School created")),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    Ok(created(format!("/api/admin/school/{}", school.id), school))
 }
 
 #[derive(Deserialize)]
@@ -56,8 +169,10 @@ struct CreateStudent {
 async fn post_create_student(
     extract::Json(payload): extract::Json<CreateStudent>,
     pool: Extension<PgPool>,
-) -> Result<Html<&'static str>, StatusCode> {
-    let mut conn = pool.get().map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Extension(user): Extension<User>,
+) -> Result<Created<Student>, ApiError> {
+    let mut conn = pool.get()?;
+    authorize(&user, &[Role::SchoolAdmin], Some(payload.school_id))?;
 
     let student = administration::create_student(
         &mut conn,
@@ -67,12 +182,12 @@ async fn post_create_student(
         payload.school_id,
         payload.group_id,
         payload.user_id,
-    );
+    )?;
 
-    match student {
-        Ok(_) => Ok(Html("Student created")),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    Ok(created(
+        format!("/api/admin/student/{}", student.id),
+        student,
+    ))
 }
 
 #[derive(Deserialize)]
@@ -80,14 +195,17 @@ struct CreateTeacher {
     pub first_name: String,
     pub last_name: String,
     pub school_id: Uuid,
-    pub user_id: Uuid,
+    /// Usually left out, the teacher gets an invitation code instead
+    pub user_id: Option<Uuid>,
 }
 
 async fn post_create_teacher(
     extract::Json(payload): extract::Json<CreateTeacher>,
     pool: Extension<PgPool>,
-) -> Result<Html<&'static str>, StatusCode> {
-    let mut conn = pool.get().map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Extension(user): Extension<User>,
+) -> Result<Created<Teacher>, ApiError> {
+    let mut conn = pool.get()?;
+    authorize(&user, &[Role::SchoolAdmin], Some(payload.school_id))?;
 
     let teacher = administration::create_teacher(
         &mut conn,
@@ -95,12 +213,12 @@ async fn post_create_teacher(
         &payload.last_name,
         payload.user_id,
         payload.school_id,
-    );
+    )?;
 
-    match teacher {
-        Ok(_) => Ok(Html("Teacher created")),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    Ok(created(
+        format!("/api/admin/teacher/{}", teacher.id),
+        teacher,
+    ))
 }
 
 #[derive(Deserialize)]
@@ -112,15 +230,17 @@ struct CreateSubject {
 async fn post_create_subject(
     extract::Json(payload): extract::Json<CreateSubject>,
     pool: Extension<PgPool>,
-) -> Result<Html<&'static str>, StatusCode> {
-    let mut conn = pool.get().map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Extension(user): Extension<User>,
+) -> Result<Created<Subject>, ApiError> {
+    let mut conn = pool.get()?;
+    authorize(&user, &[Role::SchoolAdmin], Some(payload.school_id))?;
 
-    let subject = administration::create_subject(&mut conn, &payload.name, payload.school_id);
+    let subject = administration::create_subject(&mut conn, &payload.name, payload.school_id)?;
 
-    match subject {
-        Ok(_) => Ok(Html("Subject created")),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    Ok(created(
+        format!("/api/admin/subject/{}", subject.id),
+        subject,
+    ))
 }
 
 #[derive(Deserialize)]
@@ -132,15 +252,14 @@ struct CreateGroup {
 async fn post_create_group(
     extract::Json(payload): extract::Json<CreateGroup>,
     pool: Extension<PgPool>,
-) -> Result<Html<&'static str>, StatusCode> {
-    let mut conn = pool.get().map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Extension(user): Extension<User>,
+) -> Result<Created<Group>, ApiError> {
+    let mut conn = pool.get()?;
+    authorize(&user, &[Role::SchoolAdmin], Some(payload.school_id))?;
 
-    let group = administration::create_group(&mut conn, &payload.name, payload.school_id);
+    let group = administration::create_group(&mut conn, &payload.name, payload.school_id)?;
 
-    match group {
-        Ok(_) => Ok(Html("Group created")),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    Ok(created(format!("/api/admin/group/{}", group.id), group))
 }
 
 #[derive(Deserialize)]
@@ -153,20 +272,25 @@ struct CreateClass {
 async fn post_create_class(
     extract::Json(payload): extract::Json<CreateClass>,
     pool: Extension<PgPool>,
-) -> Result<Html<&'static str>, StatusCode> {
-    let mut conn = pool.get().map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Extension(user): Extension<User>,
+) -> Result<Created<Class>, ApiError> {
+    let mut conn = pool.get()?;
+    for school in [
+        administration::school_of_group(&mut conn, payload.group_id)?,
+        administration::school_of_subject(&mut conn, payload.subject_id)?,
+        administration::school_of_teacher(&mut conn, payload.teacher_id)?,
+    ] {
+        authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+    }
 
     let class = administration::create_class(
         &mut conn,
         payload.subject_id,
         payload.group_id,
         payload.teacher_id,
-    );
+    )?;
 
-    match class {
-        Ok(_) => Ok(Html("Class created")),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    Ok(created(format!("/api/admin/class/{}", class.id), class))
 }
 
 #[derive(Deserialize)]
@@ -178,65 +302,906 @@ struct CreateClassStudent {
 async fn post_create_class_student(
     extract::Json(payload): extract::Json<CreateClassStudent>,
     pool: Extension<PgPool>,
-) -> Result<Html<&'static str>, StatusCode> {
-    let mut conn = pool.get().map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Extension(user): Extension<User>,
+) -> Result<Created<ClassStudent>, ApiError> {
+    let mut conn = pool.get()?;
+    for school in [
+        administration::school_of_class(&mut conn, payload.class_id)?,
+        administration::school_of_student(&mut conn, payload.student_id)?,
+    ] {
+        authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+    }
 
     let class_student =
-        administration::add_student_to_class(&mut conn, payload.student_id, payload.class_id);
+        administration::add_student_to_class(&mut conn, payload.student_id, payload.class_id)?;
 
-    match class_student {
-        Ok(_) => Ok(Html("Added student to class")),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    Ok(created(
+        format!(
+            "/api/admin/class-student/{}/{}",
+            class_student.class_id, class_student.student_id
+        ),
+        class_student,
+    ))
+}
+
+#[derive(Deserialize)]
+struct CreateGuardian {
+    pub user_id: Uuid,
+    pub student_id: Uuid,
+    pub relationship: Relationship,
+    #[serde(default)]
+    pub primary_contact: bool,
+    #[serde(default)]
+    pub emergency_contact: bool,
+}
+
+async fn post_create_guardian(
+    extract::Json(payload): extract::Json<CreateGuardian>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Created<Guardian>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::school_of_student(&mut conn, payload.student_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+
+    let guardian = guardians::add_guardian(
+        &mut conn,
+        &NewGuardian {
+            user_id: payload.user_id,
+            student_id: payload.student_id,
+            relationship: payload.relationship,
+            primary_contact: payload.primary_contact,
+            emergency_contact: payload.emergency_contact,
+        },
+    )?;
+
+    Ok(created(
+        format!(
+            "/api/admin/guardian/{}/{}",
+            guardian.user_id, guardian.student_id
+        ),
+        guardian,
+    ))
 }
 
 #[derive(Deserialize)]
 struct CreateGrade {
-    pub value: f64,
-    pub weight: i32,
+    /// Symbol from the school's grading scale, e.g. "4+"
+    pub symbol: String,
+    /// The task's weight if not set
+    pub weight: Option<i32>,
     pub teacher_id: Uuid,
     pub student_id: Uuid,
-    pub subject_id: Uuid,
+    /// Subject of the task's class if not set
+    pub subject_id: Option<Uuid>,
     pub task_id: Uuid,
+    /// Earlier attempt at the task, set for retakes and corrections
+    pub corrects_id: Option<Uuid>,
 }
 
 async fn post_create_grade(
     extract::Json(payload): extract::Json<CreateGrade>,
     pool: Extension<PgPool>,
-) -> Result<Html<&'static str>, StatusCode> {
-    let mut conn = pool.get().map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Extension(user): Extension<User>,
+) -> Result<Created<Grade>, ApiError> {
+    let mut conn = pool.get()?;
+    let mut schools = vec![
+        administration::school_of_student(&mut conn, payload.student_id)?,
+        administration::school_of_teacher(&mut conn, payload.teacher_id)?,
+    ];
+    if let Some(subject_id) = payload.subject_id {
+        schools.push(administration::school_of_subject(&mut conn, subject_id)?);
+    }
+    let task_school = administration::school_of_task(&mut conn, payload.task_id)?;
+    authorize_task(&user, task_school)?;
+    for school in schools {
+        authorize(&user, &[Role::SchoolAdmin, Role::Teacher], Some(school))?;
+    }
 
     let grade = administration::create_grade(
         &mut conn,
-        payload.value,
+        &payload.symbol,
         payload.weight,
         payload.teacher_id,
         payload.student_id,
         payload.subject_id,
         payload.task_id,
-    );
+        payload.corrects_id,
+        Some(user.id),
+    )?;
 
-    match grade {
-        Ok(_) => Ok(Html("Grade created")),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    Ok(created(format!("/api/admin/grade/{}", grade.id), grade))
 }
 
 #[derive(Deserialize)]
 struct CreateTask {
     pub name: String,
+    pub class_id: Uuid,
+    pub category: TaskCategory,
+    /// Default weight of the category if not set
+    pub weight: Option<i32>,
+    pub date: Date,
+    pub description: Option<String>,
+    pub max_points: Option<f64>,
 }
 
 async fn post_create_task(
     extract::Json(payload): extract::Json<CreateTask>,
     pool: Extension<PgPool>,
-) -> Result<Html<&'static str>, StatusCode> {
-    let mut conn = pool.get().map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Extension(user): Extension<User>,
+) -> Result<Created<Task>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::school_of_class(&mut conn, payload.class_id)?;
+    authorize(&user, &[Role::SchoolAdmin, Role::Teacher], Some(school))?;
+
+    let task = administration::create_task(
+        &mut conn,
+        &NewTask {
+            name: &payload.name,
+            class_id: payload.class_id,
+            category: payload.category,
+            weight: payload
+                .weight
+                .unwrap_or_else(|| payload.category.default_weight()),
+            date: payload.date,
+            description: payload.description.as_deref(),
+            max_points: payload.max_points,
+        },
+    )?;
+
+    Ok(created(format!("/api/admin/task/{}", task.id), task))
+}
+
+async fn get_schools(
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<School>>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = school_filter(&user)?;
+
+    let schools = administration::list_schools(&mut conn, school)?;
+    Ok(Json(schools))
+}
+
+async fn get_school(
+    extract::Path(school_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<School>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::get_school(&mut conn, school_id)?;
+    authorize(&user, STAFF, Some(school.id))?;
+
+    Ok(Json(school))
+}
+
+async fn patch_school(
+    extract::Path(school_id): extract::Path<Uuid>,
+    extract::Json(payload): extract::Json<UpdateSchool>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<School>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::get_school(&mut conn, school_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(school.id))?;
+
+    let school = administration::update_school(&mut conn, school_id, &payload)?;
+    Ok(Json(school))
+}
+
+async fn get_grading_scale(
+    extract::Path(school_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<GradeSymbol>>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::get_school(&mut conn, school_id)?;
+    authorize(&user, STAFF, Some(school.id))?;
+
+    let scale = grading::get_scale(&mut conn, school.id)?;
+    Ok(Json(scale))
+}
+
+/// Replaces the whole scale, grades given earlier keep their symbols
+async fn put_grading_scale(
+    extract::Path(school_id): extract::Path<Uuid>,
+    extract::Json(payload): extract::Json<Vec<GradeSymbol>>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<GradeSymbol>>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::get_school(&mut conn, school_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(school.id))?;
+
+    let scale = grading::set_scale(&mut conn, school.id, payload)?;
+    Ok(Json(scale))
+}
+
+async fn delete_school(
+    extract::Path(school_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<School>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::get_school(&mut conn, school_id)?;
+    authorize(&user, &[], Some(school.id))?;
+
+    let school = administration::delete_school(&mut conn, school_id)?;
+    Ok(Json(school))
+}
+
+async fn get_students(
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Student>>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = school_filter(&user)?;
 
-    let task = administration::create_task(&mut conn, &payload.name);
+    let students = administration::list_students(&mut conn, school)?;
+    Ok(Json(students))
+}
 
-    match task {
-        Ok(_) => Ok(Html("Task created")),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+async fn get_student(
+    extract::Path(student_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Student>, ApiError> {
+    let mut conn = pool.get()?;
+    let student = administration::get_student(&mut conn, student_id)?;
+    authorize(&user, STAFF, Some(student.school_id))?;
+
+    Ok(Json(student))
+}
+
+async fn patch_student(
+    extract::Path(student_id): extract::Path<Uuid>,
+    extract::Json(payload): extract::Json<UpdateStudent>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Student>, ApiError> {
+    let mut conn = pool.get()?;
+    let student = administration::get_student(&mut conn, student_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(student.school_id))?;
+    if let Some(group_id) = payload.group_id {
+        let school = administration::school_of_group(&mut conn, group_id)?;
+        authorize(&user, &[Role::SchoolAdmin], Some(school))?;
     }
+
+    let student = administration::update_student(&mut conn, student_id, &payload)?;
+    Ok(Json(student))
+}
+
+async fn delete_student(
+    extract::Path(student_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Student>, ApiError> {
+    let mut conn = pool.get()?;
+    let student = administration::get_student(&mut conn, student_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(student.school_id))?;
+
+    let student = administration::delete_student(&mut conn, student_id)?;
+    Ok(Json(student))
+}
+
+async fn get_teachers(
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Teacher>>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = school_filter(&user)?;
+
+    let teachers = administration::list_teachers(&mut conn, school)?;
+    Ok(Json(teachers))
+}
+
+async fn get_teacher(
+    extract::Path(teacher_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Teacher>, ApiError> {
+    let mut conn = pool.get()?;
+    let teacher = administration::get_teacher(&mut conn, teacher_id)?;
+    authorize(&user, STAFF, Some(teacher.school_id))?;
+
+    Ok(Json(teacher))
+}
+
+async fn patch_teacher(
+    extract::Path(teacher_id): extract::Path<Uuid>,
+    extract::Json(payload): extract::Json<UpdateTeacher>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Teacher>, ApiError> {
+    let mut conn = pool.get()?;
+    let teacher = administration::get_teacher(&mut conn, teacher_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(teacher.school_id))?;
+
+    let teacher = administration::update_teacher(&mut conn, teacher_id, &payload)?;
+    Ok(Json(teacher))
+}
+
+async fn delete_teacher(
+    extract::Path(teacher_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Teacher>, ApiError> {
+    let mut conn = pool.get()?;
+    let teacher = administration::get_teacher(&mut conn, teacher_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(teacher.school_id))?;
+
+    let teacher = administration::delete_teacher(&mut conn, teacher_id)?;
+    Ok(Json(teacher))
+}
+
+async fn get_subjects(
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Subject>>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = school_filter(&user)?;
+
+    let subjects = administration::list_subjects(&mut conn, school)?;
+    Ok(Json(subjects))
+}
+
+async fn get_subject(
+    extract::Path(subject_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Subject>, ApiError> {
+    let mut conn = pool.get()?;
+    let subject = administration::get_subject(&mut conn, subject_id)?;
+    authorize(&user, STAFF, Some(subject.school_id))?;
+
+    Ok(Json(subject))
+}
+
+async fn patch_subject(
+    extract::Path(subject_id): extract::Path<Uuid>,
+    extract::Json(payload): extract::Json<UpdateSubject>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Subject>, ApiError> {
+    let mut conn = pool.get()?;
+    let subject = administration::get_subject(&mut conn, subject_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(subject.school_id))?;
+
+    let subject = administration::update_subject(&mut conn, subject_id, &payload)?;
+    Ok(Json(subject))
+}
+
+async fn delete_subject(
+    extract::Path(subject_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Subject>, ApiError> {
+    let mut conn = pool.get()?;
+    let subject = administration::get_subject(&mut conn, subject_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(subject.school_id))?;
+
+    let subject = administration::delete_subject(&mut conn, subject_id)?;
+    Ok(Json(subject))
+}
+
+async fn get_groups(
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Group>>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = school_filter(&user)?;
+
+    let groups = administration::list_groups(&mut conn, school)?;
+    Ok(Json(groups))
+}
+
+async fn get_group(
+    extract::Path(group_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Group>, ApiError> {
+    let mut conn = pool.get()?;
+    let group = administration::get_group(&mut conn, group_id)?;
+    authorize(&user, STAFF, Some(group.school_id))?;
+
+    Ok(Json(group))
+}
+
+async fn patch_group(
+    extract::Path(group_id): extract::Path<Uuid>,
+    extract::Json(payload): extract::Json<UpdateGroup>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Group>, ApiError> {
+    let mut conn = pool.get()?;
+    let group = administration::get_group(&mut conn, group_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(group.school_id))?;
+
+    let group = administration::update_group(&mut conn, group_id, &payload)?;
+    Ok(Json(group))
+}
+
+async fn delete_group(
+    extract::Path(group_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Group>, ApiError> {
+    let mut conn = pool.get()?;
+    let group = administration::get_group(&mut conn, group_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(group.school_id))?;
+
+    let group = administration::delete_group(&mut conn, group_id)?;
+    Ok(Json(group))
+}
+
+#[derive(Deserialize)]
+struct TaskQuery {
+    pub class_id: Option<Uuid>,
+}
+
+async fn get_tasks(
+    extract::Query(query): extract::Query<TaskQuery>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Task>>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = school_filter(&user)?;
+
+    let tasks = administration::list_tasks(&mut conn, school, query.class_id)?;
+    Ok(Json(tasks))
+}
+
+async fn get_task(
+    extract::Path(task_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Task>, ApiError> {
+    let mut conn = pool.get()?;
+    let task = administration::get_task(&mut conn, task_id)?;
+    let school = administration::school_of_task(&mut conn, task.id)?;
+    authorize_task(&user, school)?;
+
+    Ok(Json(task))
+}
+
+async fn patch_task(
+    extract::Path(task_id): extract::Path<Uuid>,
+    extract::Json(payload): extract::Json<UpdateTask>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Task>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::school_of_task(&mut conn, task_id)?;
+    authorize_task(&user, school)?;
+    if let Some(class_id) = payload.class_id {
+        let school = administration::school_of_class(&mut conn, class_id)?;
+        authorize(&user, STAFF, Some(school))?;
+    }
+
+    let task = administration::update_task(&mut conn, task_id, &payload)?;
+    Ok(Json(task))
+}
+
+async fn delete_task(
+    extract::Path(task_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Task>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::school_of_task(&mut conn, task_id)?;
+    authorize_task(&user, school)?;
+
+    let task = administration::delete_task(&mut conn, task_id)?;
+    Ok(Json(task))
+}
+
+async fn get_classes(
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Class>>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = school_filter(&user)?;
+
+    let classes = administration::list_classes(&mut conn, school)?;
+    Ok(Json(classes))
+}
+
+async fn get_class(
+    extract::Path(class_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Class>, ApiError> {
+    let mut conn = pool.get()?;
+    let class = administration::get_class(&mut conn, class_id)?;
+    let school = administration::school_of_class(&mut conn, class_id)?;
+    authorize(&user, STAFF, Some(school))?;
+
+    Ok(Json(class))
+}
+
+async fn patch_class(
+    extract::Path(class_id): extract::Path<Uuid>,
+    extract::Json(payload): extract::Json<UpdateClass>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Class>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::school_of_class(&mut conn, class_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+    if let Some(subject_id) = payload.subject_id {
+        let school = administration::school_of_subject(&mut conn, subject_id)?;
+        authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+    }
+    if let Some(group_id) = payload.group_id {
+        let school = administration::school_of_group(&mut conn, group_id)?;
+        authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+    }
+    if let Some(teacher_id) = payload.teacher_id {
+        let school = administration::school_of_teacher(&mut conn, teacher_id)?;
+        authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+    }
+
+    let class = administration::update_class(&mut conn, class_id, &payload)?;
+    Ok(Json(class))
+}
+
+async fn delete_class(
+    extract::Path(class_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Class>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::school_of_class(&mut conn, class_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+
+    let class = administration::delete_class(&mut conn, class_id)?;
+    Ok(Json(class))
+}
+
+#[derive(Deserialize)]
+struct ClassStudentQuery {
+    pub class_id: Option<Uuid>,
+}
+
+async fn get_class_students(
+    extract::Query(query): extract::Query<ClassStudentQuery>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<ClassStudent>>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = school_filter(&user)?;
+
+    let class_students = administration::list_class_students(&mut conn, school, query.class_id)?;
+    Ok(Json(class_students))
+}
+
+async fn get_class_student(
+    extract::Path((class_id, student_id)): extract::Path<(Uuid, Uuid)>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<ClassStudent>, ApiError> {
+    let mut conn = pool.get()?;
+    let class_student = administration::get_class_student(&mut conn, class_id, student_id)?;
+    let school = administration::school_of_student(&mut conn, student_id)?;
+    authorize(&user, STAFF, Some(school))?;
+
+    Ok(Json(class_student))
+}
+
+async fn delete_class_student(
+    extract::Path((class_id, student_id)): extract::Path<(Uuid, Uuid)>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<ClassStudent>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::school_of_student(&mut conn, student_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+
+    let class_student = administration::remove_student_from_class(&mut conn, class_id, student_id)?;
+    Ok(Json(class_student))
+}
+
+#[derive(Deserialize)]
+struct GuardianQuery {
+    pub student_id: Option<Uuid>,
+}
+
+async fn get_guardians(
+    extract::Query(query): extract::Query<GuardianQuery>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Guardian>>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = school_filter(&user)?;
+
+    let guardians = guardians::list_guardians(&mut conn, school, query.student_id)?;
+    Ok(Json(guardians))
+}
+
+async fn get_guardian(
+    extract::Path((user_id, student_id)): extract::Path<(Uuid, Uuid)>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Guardian>, ApiError> {
+    let mut conn = pool.get()?;
+    let guardian = guardians::get_guardian(&mut conn, user_id, student_id)?;
+    let school = administration::school_of_student(&mut conn, student_id)?;
+    authorize(&user, STAFF, Some(school))?;
+
+    Ok(Json(guardian))
+}
+
+async fn patch_guardian(
+    extract::Path((user_id, student_id)): extract::Path<(Uuid, Uuid)>,
+    extract::Json(payload): extract::Json<UpdateGuardian>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Guardian>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::school_of_student(&mut conn, student_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+
+    let guardian = guardians::update_guardian(&mut conn, user_id, student_id, &payload)?;
+    Ok(Json(guardian))
+}
+
+async fn delete_guardian(
+    extract::Path((user_id, student_id)): extract::Path<(Uuid, Uuid)>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Guardian>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = administration::school_of_student(&mut conn, student_id)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+
+    let guardian = guardians::remove_guardian(&mut conn, user_id, student_id)?;
+    Ok(Json(guardian))
+}
+
+#[derive(Deserialize)]
+struct GradeQuery {
+    pub student_id: Option<Uuid>,
+}
+
+async fn get_grades(
+    extract::Query(query): extract::Query<GradeQuery>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Grade>>, ApiError> {
+    let mut conn = pool.get()?;
+    let school = school_filter(&user)?;
+
+    let grades = administration::list_grades(&mut conn, school, query.student_id)?;
+    Ok(Json(grades))
+}
+
+async fn get_grade(
+    extract::Path(grade_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Grade>, ApiError> {
+    let mut conn = pool.get()?;
+    let grade = administration::get_grade(&mut conn, grade_id)?;
+    let school = administration::school_of_student(&mut conn, grade.student_id)?;
+    authorize(&user, STAFF, Some(school))?;
+
+    Ok(Json(grade))
+}
+
+#[derive(Deserialize)]
+struct PatchGrade {
+    #[serde(flatten)]
+    changes: UpdateGrade,
+    /// Kept in the grade's history
+    reason: String,
+}
+
+async fn patch_grade(
+    extract::Path(grade_id): extract::Path<Uuid>,
+    extract::Json(payload): extract::Json<PatchGrade>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Grade>, ApiError> {
+    let mut conn = pool.get()?;
+    let grade = administration::get_grade(&mut conn, grade_id)?;
+    let school = administration::school_of_student(&mut conn, grade.student_id)?;
+    authorize(&user, STAFF, Some(school))?;
+    if let Some(teacher_id) = payload.changes.teacher_id {
+        let school = administration::school_of_teacher(&mut conn, teacher_id)?;
+        authorize(&user, STAFF, Some(school))?;
+    }
+
+    let grade = administration::update_grade(
+        &mut conn,
+        grade_id,
+        &payload.changes,
+        user.id,
+        &payload.reason,
+    )?;
+    Ok(Json(grade))
+}
+
+#[derive(Deserialize)]
+struct ReasonQuery {
+    pub reason: String,
+}
+
+async fn delete_grade(
+    extract::Path(grade_id): extract::Path<Uuid>,
+    extract::Query(query): extract::Query<ReasonQuery>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Grade>, ApiError> {
+    let mut conn = pool.get()?;
+    let grade = administration::get_grade(&mut conn, grade_id)?;
+    let school = administration::school_of_student(&mut conn, grade.student_id)?;
+    authorize(&user, STAFF, Some(school))?;
+
+    let grade = administration::delete_grade(&mut conn, grade_id, user.id, &query.reason)?;
+    Ok(Json(grade))
+}
+
+/// History of a grade, also of a deleted one
+async fn get_grade_history(
+    extract::Path(grade_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<GradeHistoryEntry>>, ApiError> {
+    let mut conn = pool.get()?;
+    let history = grading::grade_history(&mut conn, grade_id)?;
+    let school = administration::school_of_student(&mut conn, history[0].student_id)?;
+    authorize(&user, STAFF, Some(school))?;
+
+    Ok(Json(history))
+}
+
+/// Exactly one of the ids has to be set
+#[derive(Deserialize)]
+struct CreateInvitation {
+    pub student_id: Option<Uuid>,
+    pub teacher_id: Option<Uuid>,
+}
+
+#[derive(Serialize)]
+struct CreatedInvitation {
+    #[serde(flatten)]
+    invitation: Invitation,
+    /// Shown only once
+    code: String,
+}
+
+async fn post_create_invitation(
+    extract::Json(payload): extract::Json<CreateInvitation>,
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+    Extension(user): Extension<User>,
+) -> Result<Created<CreatedInvitation>, ApiError> {
+    let mut conn = pool.get()?;
+    let (target, school) = match (payload.student_id, payload.teacher_id) {
+        (Some(student), None) => (
+            Target::Student(student),
+            administration::school_of_student(&mut conn, student)?,
+        ),
+        (None, Some(teacher)) => (
+            Target::Teacher(teacher),
+            administration::school_of_teacher(&mut conn, teacher)?,
+        ),
+        _ => {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_target",
+                "Invite either a student or a teacher",
+            ))
+        }
+    };
+    authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+
+    let (invitation, code) =
+        invitations::create_invitation(&mut conn, user.id, target, config.tokens.invitation)?;
+    Ok(created(
+        format!("/api/admin/invitation/{}", invitation.id),
+        CreatedInvitation { invitation, code },
+    ))
+}
+
+async fn get_invitations(
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Invitation>>, ApiError> {
+    let mut conn = pool.get()?;
+    authorize(&user, &[Role::SchoolAdmin], None)?;
+    let school = school_filter(&user)?;
+
+    let invitations = invitations::list_invitations(&mut conn, school)?;
+    Ok(Json(invitations))
+}
+
+async fn get_invitation(
+    extract::Path(invitation_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Invitation>, ApiError> {
+    let mut conn = pool.get()?;
+    let invitation = invitations::get_invitation(&mut conn, invitation_id)?;
+    let school = invitations::school_of_invitation(&mut conn, &invitation)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+
+    Ok(Json(invitation))
+}
+
+/// Revokes an invitation, redeemed ones can't be revoked
+async fn delete_invitation(
+    extract::Path(invitation_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Invitation>, ApiError> {
+    let mut conn = pool.get()?;
+    let invitation = invitations::get_invitation(&mut conn, invitation_id)?;
+    let school = invitations::school_of_invitation(&mut conn, &invitation)?;
+    authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+
+    let invitation = invitations::revoke_invitation(&mut conn, invitation_id)?;
+    Ok(Json(invitation))
+}
+
+#[derive(Deserialize)]
+struct AssignRole {
+    pub user_id: Uuid,
+    pub role: Role,
+    pub school_id: Option<Uuid>,
+}
+
+async fn post_assign_role(
+    extract::Json(payload): extract::Json<AssignRole>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<User>, ApiError> {
+    let mut conn = pool.get()?;
+    // only system admins can grant system admin or assign users outside of any school
+    match payload.school_id {
+        Some(school) if payload.role != Role::SystemAdmin => {
+            authorize(&user, &[Role::SchoolAdmin], Some(school))?
+        }
+        _ => authorize(&user, &[], None)?,
+    }
+    // school admins manage users of their school and users without one, never system admins
+    let target = auth::get_by_id(&mut conn, payload.user_id)
+        .map_err(AuthError::from)?
+        .ok_or(AuthError::UserNotFound)?;
+    if target.role == Role::SystemAdmin {
+        authorize(&user, &[], None)?;
+    } else if let Some(school) = target.school_id {
+        authorize(&user, &[Role::SchoolAdmin], Some(school))?;
+    }
+
+    let user = auth::set_role(&mut conn, payload.user_id, payload.role, payload.school_id)?;
+    Ok(Json(user))
+}
+
+#[derive(Deserialize)]
+struct Impersonate {
+    user_id: Uuid,
+}
+
+/// Replaces the admin's session cookie with a session of another user,
+/// logging out ends the impersonation
+async fn post_impersonate(
+    extract::Json(payload): extract::Json<Impersonate>,
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+    Extension(user): Extension<User>,
+    session: Option<Extension<Session>>,
+    ClientIp(ip): ClientIp,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, Json<User>), ApiError> {
+    if session.is_none() {
+        return Err(AuthError::SessionRequired.into());
+    }
+    let mut conn = pool.get()?;
+
+    let (session_id, target) = auth::start_impersonation(&mut conn, &user, payload.user_id, ip)?;
+    Ok((
+        jar.add(session_cookie(session_id, false, &config)),
+        Json(target),
+    ))
 }
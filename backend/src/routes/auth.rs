@@ -1,23 +1,32 @@
+use super::{extract, is_safe, request_cookies, verify_signed, ClientIp};
 use crate::{
+    api_tokens, audit,
     auth::{
-        create_session, login_user, try_change_pass, try_create_new_user, try_get_session,
-        AuthError,
+        create_session, delete_session, delete_user_sessions, get_by_id, get_user_sessions,
+        is_impersonation, issue_token, login_user, request_email_verification,
+        request_password_reset, revoke_user_session, try_change_pass, try_create_new_user,
+        try_get_session, try_reset_pass, verify_email, AuthError, PgConn, EMAIL_VERIFICATION,
     },
+    config::{Config, CookieConfig},
     database::PgPool,
-    models::User,
+    errors::ApiError,
+    invitations, lockout,
+    mail::{Mail, SharedMailer},
+    models::{ApiToken, NewAuditEntry, Scope, Session, User},
+    two_factor::{self, Enrollment},
 };
 use axum::{
-    extract,
-    http::{self, Request, StatusCode},
+    extract::{OriginalUri, RequestParts},
+    http::{self, HeaderMap, HeaderValue, Request, StatusCode},
     middleware::Next,
-    response::{Html, Response},
-    routing::{get, post},
-    Extension, Router,
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get, post},
+    Extension, Json, Router,
 };
-use axum_extra::extract::{cookie::Cookie, CookieJar};
+use axum_extra::extract::{cookie::Cookie, SignedCookieJar};
+use diesel::Connection;
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
-use time::Duration;
+use std::{net::IpAddr, str::FromStr, time::SystemTime};
 use uuid::Uuid;
 
 pub fn router() -> Router {
@@ -25,9 +34,41 @@ pub fn router() -> Router {
         .route("/register", post(post_register_user))
         .route("/login", post(post_login_user))
         .route("/change-pass", post(post_change_pass))
+        .route("/forgot-pass", post(post_forgot_pass))
+        .route("/reset-pass", post(post_reset_pass))
+        .route("/verify-email", post(post_verify_email))
+        .route("/resend-verification", post(post_resend_verification))
+        .route("/confirm-email", post(super::account::post_confirm_email))
+        .route("/login/2fa", post(post_login_two_factor))
+        .route("/csrf", get(super::csrf::get_csrf_token))
+        .route("/oidc/login", get(super::oidc::get_login))
+        .route("/oidc/callback", get(super::oidc::get_callback))
         .merge(
             Router::new()
                 .route("/greet", get(greet))
+                .merge(
+                    Router::new()
+                        .route("/logout", post(post_logout))
+                        .route("/sessions", get(get_sessions))
+                        .merge(
+                            Router::new()
+                                .route("/logout-all", post(post_logout_all))
+                                .route("/sessions/:id", delete(delete_user_session))
+                                .route("/2fa/setup", post(post_two_factor_setup))
+                                .route("/2fa/enable", post(post_two_factor_enable))
+                                .route("/2fa/disable", post(post_two_factor_disable))
+                                .route("/tokens", get(get_api_tokens).post(post_create_api_token))
+                                .route("/tokens/:id", delete(delete_api_token))
+                                .route(
+                                    "/profile",
+                                    get(super::account::get_profile)
+                                        .patch(super::account::patch_profile)
+                                        .delete(super::account::delete_profile),
+                                )
+                                .route_layer(axum::middleware::from_fn(forbid_impersonation)),
+                        )
+                        .route_layer(axum::middleware::from_fn(require_session)),
+                )
                 .route_layer(axum::middleware::from_fn(middleware)),
         )
 }
@@ -37,23 +78,104 @@ struct AuthUser {
     login: String,
     email: String,
     password: String,
+    /// Code from [`invitations`], links the new account to a student or teacher
+    #[serde(default)]
+    invitation: Option<String>,
 }
 
 async fn post_register_user(
     extract::Json(payload): extract::Json<AuthUser>,
     pool: Extension<PgPool>,
-) -> Result<Html<&'static str>, StatusCode> {
-    let mut conn = pool.get().map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
+    config: Extension<Config>,
+    mailer: Extension<SharedMailer>,
+) -> Result<Html<&'static str>, ApiError> {
+    let mut conn = pool.get()?;
 
-    match try_create_new_user(&mut conn, &payload.login, &payload.email, &payload.password) {
-        Ok(_) => Ok(Html("<h1>Registered</h1>")),
-        Err(AuthError::UserAlreadyExists) => Err(StatusCode::BAD_REQUEST),
-        Err(AuthError::WeakPassword) => Err(StatusCode::BAD_REQUEST),
-        Err(AuthError::Unexpected(_)) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-        _ => Err(StatusCode::NOT_IMPLEMENTED),
+    // an invalid invitation code doesn't leave a half-registered account behind
+    let user = conn.transaction::<_, ApiError, _>(|conn| {
+        let user = try_create_new_user(
+            conn,
+            &payload.login,
+            &payload.email,
+            &payload.password,
+            &config.password,
+        )?;
+        match &payload.invitation {
+            Some(code) => Ok(invitations::redeem(conn, code, &user)?),
+            None => Ok(user),
+        }
+    })?;
+    let token = issue_token(
+        &mut conn,
+        user.id,
+        EMAIL_VERIFICATION,
+        config.tokens.email_verification,
+    )
+    .map_err(ApiError::internal)?;
+    send_mail(&mailer, verification_mail(&config, user, &token)).await?;
+
+    Ok(Html(
+        "<h1>Registered, check your mailbox to verify the email address</h1>",
+    ))
+}
+
+fn verification_mail(config: &Config, user: User, token: &str) -> Mail {
+    Mail {
+        to: user.email,
+        subject: "Confirm your Bibrus account".to_string(),
+        body: format!(
+            "Hello {},\n\nconfirm your email address using this link:\n{}/verify-email?token={}\n\n\
+            The link expires in {} hours. If you didn't create an account, ignore this message.",
+            user.login,
+            config.app_url,
+            token,
+            config.tokens.email_verification.whole_hours()
+        ),
     }
 }
 
+#[derive(Deserialize)]
+struct VerifyEmail {
+    token: String,
+}
+
+async fn post_verify_email(
+    extract::Json(payload): extract::Json<VerifyEmail>,
+    pool: Extension<PgPool>,
+) -> Result<Html<&'static str>, ApiError> {
+    let mut conn = pool.get()?;
+
+    verify_email(&mut conn, &payload.token)?;
+    Ok(Html("<h1>Email verified</h1>"))
+}
+
+#[derive(Deserialize)]
+struct ResendVerification {
+    email: String,
+}
+
+async fn post_resend_verification(
+    extract::Json(payload): extract::Json<ResendVerification>,
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+    mailer: Extension<SharedMailer>,
+) -> Result<Html<&'static str>, ApiError> {
+    let mut conn = pool.get()?;
+
+    let verification =
+        request_email_verification(&mut conn, &payload.email, config.tokens.email_verification)
+            .map_err(|e| ApiError::internal(format!("{e:#}")))?;
+
+    // mailed in the background, unknown addresses get their answer just as fast
+    if let Some((user, token)) = verification {
+        send_mail_detached(&mailer, verification_mail(&config, user, &token));
+    }
+
+    Ok(Html(
+        "<h1>If this address awaits verification, a new link has been sent</h1>",
+    ))
+}
+
 #[derive(Deserialize)]
 struct ChangePass {
     login: String,
@@ -64,70 +186,637 @@ struct ChangePass {
 async fn post_change_pass(
     extract::Json(payload): extract::Json<ChangePass>,
     pool: Extension<PgPool>,
-) -> Result<Html<&'static str>, StatusCode> {
-    let mut conn = pool.get().map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
+    config: Extension<Config>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> Result<Html<&'static str>, ApiError> {
+    let mut conn = pool.get()?;
 
-    match try_change_pass(&mut conn, &payload.login, &payload.pass, &payload.new_pass) {
-        Ok(_) => Ok(Html("<h1>Password changed</h1>")),
-        Err(AuthError::UserNotFound) => Err(StatusCode::NOT_FOUND),
-        Err(AuthError::IncorrectPassword) => Err(StatusCode::FORBIDDEN),
-        Err(AuthError::WeakPassword) => Err(StatusCode::BAD_REQUEST),
-        Err(AuthError::Unexpected(_)) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-        _ => Err(StatusCode::NOT_IMPLEMENTED),
+    // the route works without a session, but an admin acting as someone mustn't use it
+    if let Some((session_id, _)) = session_id_from_cookies(&headers, &config.cookies) {
+        if is_impersonation(&mut conn, session_id).map_err(ApiError::internal)? {
+            return Err(AuthError::ImpersonationForbidden.into());
+        }
     }
+    lockout::check(&mut conn, Some(&payload.login), ip, &config.lockout)?;
+
+    let result = try_change_pass(
+        &mut conn,
+        &payload.login,
+        &payload.pass,
+        &payload.new_pass,
+        &config.password,
+    );
+    track_failure(&mut conn, Some(&payload.login), ip, &config, result).map_err(|e| match e {
+        AuthError::WeakPassword => ApiError::weak_password("new_pass"),
+        e => e.into(),
+    })?;
+    Ok(Html("<h1>Password changed</h1>"))
+}
+
+/// Counts failed credential checks towards the lockout. Every wrong login or password
+/// gets the same answer, so that it doesn't reveal which logins exist.
+pub(super) fn track_failure<T>(
+    conn: &mut PgConn,
+    user_login: Option<&str>,
+    ip: IpAddr,
+    config: &Config,
+    result: Result<T, AuthError>,
+) -> Result<T, AuthError> {
+    match result {
+        Ok(value) => {
+            if let Some(user_login) = user_login {
+                lockout::clear(conn, user_login)?;
+            }
+            Ok(value)
+        }
+        Err(
+            AuthError::UserNotFound | AuthError::IncorrectPassword | AuthError::InvalidCredentials,
+        ) => {
+            lockout::record_failure(conn, user_login, ip, &config.lockout)?;
+            Err(AuthError::InvalidCredentials)
+        }
+        Err(AuthError::InvalidTwoFactorCode) => {
+            lockout::record_failure(conn, user_login, ip, &config.lockout)?;
+            Err(AuthError::InvalidTwoFactorCode)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Sends mail on a blocking thread, SMTP may take a while
+pub(super) async fn send_mail(mailer: &SharedMailer, mail: Mail) -> Result<(), ApiError> {
+    let mailer = mailer.clone();
+    tokio::task::spawn_blocking(move || mailer.send(&mail))
+        .await
+        .map_err(ApiError::internal)?
+        .map_err(|e| ApiError::internal(format!("{e:#}")))
+}
+
+/// Sends mail without waiting for it, for answers which mustn't reveal whether mail was sent
+fn send_mail_detached(mailer: &SharedMailer, mail: Mail) {
+    let mailer = mailer.clone();
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = mailer.send(&mail) {
+            tracing::error!("Failed to send mail to {}: {e:#}", mail.to);
+        }
+    });
+}
+
+#[derive(Deserialize)]
+struct ForgotPass {
+    email: String,
+}
+
+async fn post_forgot_pass(
+    extract::Json(payload): extract::Json<ForgotPass>,
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+    mailer: Extension<SharedMailer>,
+) -> Result<Html<&'static str>, ApiError> {
+    let mut conn = pool.get()?;
+
+    let lifetime = config.tokens.password_reset;
+    let reset = request_password_reset(&mut conn, &payload.email, lifetime)
+        .map_err(|e| ApiError::internal(format!("{e:#}")))?;
+
+    // answers the same way and as fast for unknown addresses, so that they can't be discovered
+    if let Some((user, token)) = reset {
+        let mail = Mail {
+            to: user.email,
+            subject: "Bibrus password reset".to_string(),
+            body: format!(
+                "Hello {},\n\nuse this link to set a new password:\n{}/reset-pass?token={}\n\n\
+                The link expires in {} minutes. If you didn't ask for it, ignore this message.",
+                user.login,
+                config.app_url,
+                token,
+                lifetime.whole_minutes()
+            ),
+        };
+        send_mail_detached(&mailer, mail);
+    }
+
+    Ok(Html(
+        "<h1>If this address is registered, a reset link has been sent</h1>",
+    ))
+}
+
+#[derive(Deserialize)]
+struct ResetPass {
+    token: String,
+    new_pass: String,
+}
+
+async fn post_reset_pass(
+    extract::Json(payload): extract::Json<ResetPass>,
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+) -> Result<Html<&'static str>, ApiError> {
+    let mut conn = pool.get()?;
+
+    try_reset_pass(
+        &mut conn,
+        &payload.token,
+        &payload.new_pass,
+        &config.password,
+    )
+    .map_err(|e| match e {
+        AuthError::WeakPassword => ApiError::weak_password("new_pass"),
+        e => e.into(),
+    })?;
+    Ok(Html("<h1>Password changed</h1>"))
+}
+
+#[derive(Deserialize)]
+struct LoginUser {
+    login: String,
+    password: String,
+    /// Keeps the session alive for much longer, see [`SessionConfig`](crate::config::SessionConfig)
+    #[serde(default)]
+    remember: bool,
+    /// Code from [`invitations`], links the account to a student or teacher
+    #[serde(default)]
+    invitation: Option<String>,
+}
+
+/// Returned instead of a session when the user has to enter a second factor at `/login/2fa`
+#[derive(Serialize)]
+struct PendingLoginBody {
+    pending_login: Uuid,
 }
 
 async fn post_login_user(
-    extract::Json(payload): extract::Json<AuthUser>,
+    extract::Json(payload): extract::Json<LoginUser>,
     pool: Extension<PgPool>,
-    jar: CookieJar,
-) -> Result<(CookieJar, Html<&'static str>), StatusCode> {
-    let mut conn = pool.get().map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
-    match login_user(&mut conn, &payload.login, &payload.password) {
-        Ok(user_id) => {
-            let session_id = create_session(&mut conn, user_id)
-                .map_err(|_e| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-            let cookie = Cookie::build("session_id", session_id.to_string())
-                .path("/")
-                .http_only(true)
-                .secure(false)
-                .max_age(Duration::minutes(10))
-                .finish();
-            Ok((jar.add(cookie), Html("<h1>Logged in</h1>")))
+    config: Extension<Config>,
+    ClientIp(ip): ClientIp,
+    jar: SignedCookieJar,
+) -> Result<Response, ApiError> {
+    let mut conn = pool.get()?;
+    lockout::check(&mut conn, Some(&payload.login), ip, &config.lockout)?;
+
+    let result = login_user(
+        &mut conn,
+        &payload.login,
+        &payload.password,
+        &config.password,
+    );
+    let user = match result {
+        // failures are only forgiven once the second factor is in as well
+        Ok(user) if user.totp_enabled_at.is_some() => user,
+        result => track_failure(&mut conn, Some(&payload.login), ip, &config, result)?,
+    };
+
+    let invitation_hash = payload.invitation.as_deref().map(invitations::code_hash);
+    if user.totp_enabled_at.is_some() {
+        let pending_login = two_factor::begin_login(
+            &mut conn,
+            user.id,
+            payload.remember,
+            invitation_hash.as_deref(),
+        )
+        .map_err(ApiError::internal)?;
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(PendingLoginBody { pending_login }),
+        )
+            .into_response());
+    }
+
+    let session_id = start_session(
+        &mut conn,
+        &user,
+        payload.remember,
+        invitation_hash.as_deref(),
+    )?;
+    Ok((
+        jar.add(session_cookie(session_id, payload.remember, &config)),
+        Html("<h1>Logged in</h1>"),
+    )
+        .into_response())
+}
+
+/// Creates the session of a user who passed every login step, then redeems the
+/// invitation they logged in with. A refused invitation leaves no session behind.
+fn start_session(
+    conn: &mut PgConn,
+    user: &User,
+    remember: bool,
+    invitation_hash: Option<&str>,
+) -> Result<Uuid, ApiError> {
+    let session_id = create_session(conn, user.id, remember).map_err(ApiError::internal)?;
+    if let Some(hash) = invitation_hash {
+        if let Err(e) = invitations::redeem_hash(conn, hash, user) {
+            delete_session(conn, session_id).map_err(ApiError::internal)?;
+            return Err(e.into());
         }
-        Err(AuthError::UserNotFound) => Err(StatusCode::UNAUTHORIZED),
-        Err(AuthError::IncorrectPassword) => Err(StatusCode::UNAUTHORIZED),
-        _ => Err(StatusCode::NOT_IMPLEMENTED),
     }
+    Ok(session_id)
+}
+
+pub const SESSION_COOKIE: &str = "session_id";
+
+pub(super) fn session_cookie(session_id: Uuid, remember: bool, config: &Config) -> Cookie<'static> {
+    let mut cookie = Cookie::build(SESSION_COOKIE, session_id.to_string())
+        .path("/")
+        .http_only(true)
+        .secure(config.cookies.secure)
+        .same_site(config.cookies.same_site)
+        .finish();
+    // without "remember me" the cookie lasts until the browser is closed
+    if remember {
+        cookie.set_max_age(config.session.remember_absolute_timeout);
+    }
+    cookie
+}
+
+#[derive(Deserialize)]
+struct LoginTwoFactor {
+    pending_login: Uuid,
+    /// Code from the authenticator app or one of the recovery codes
+    code: String,
 }
 
-pub async fn middleware<B>(mut req: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
+async fn post_login_two_factor(
+    extract::Json(payload): extract::Json<LoginTwoFactor>,
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+    ClientIp(ip): ClientIp,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, Html<&'static str>), ApiError> {
+    let mut conn = pool.get()?;
+    // wrong codes count against the account too, not only against the address they come from
+    let owner = two_factor::pending_login_owner(&mut conn, payload.pending_login)
+        .map_err(ApiError::internal)?;
+    lockout::check(&mut conn, owner.as_deref(), ip, &config.lockout)?;
+
+    let result = two_factor::finish_login(
+        &mut conn,
+        payload.pending_login,
+        &payload.code,
+        &config.two_factor,
+    );
+    let pending = track_failure(&mut conn, owner.as_deref(), ip, &config, result)?;
+    let user = get_by_id(&mut conn, pending.user_id)
+        .map_err(ApiError::internal)?
+        .ok_or_else(|| ApiError::internal("User of a pending login is gone"))?;
+
+    let session_id = start_session(
+        &mut conn,
+        &user,
+        pending.remember,
+        pending.invitation_hash.as_deref(),
+    )?;
+    Ok((
+        jar.add(session_cookie(session_id, pending.remember, &config)),
+        Html("<h1>Logged in</h1>"),
+    ))
+}
+
+pub async fn middleware<B: Send>(mut req: Request<B>, next: Next<B>) -> Result<Response, ApiError> {
     let pool = req
         .extensions()
         .get::<PgPool>()
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let cookie_header = req
-        .headers()
-        .get(http::header::COOKIE)
-        .ok_or(StatusCode::UNAUTHORIZED)?;
-    let cookie = Cookie::parse(
-        cookie_header
-            .to_str()
-            .map_err(|_| StatusCode::UNAUTHORIZED)?,
-    )
-    .map_err(|_| StatusCode::UNAUTHORIZED)?;
+        .cloned()
+        .ok_or_else(|| ApiError::internal("Missing connection pool extension"))?;
+    let config = req
+        .extensions()
+        .get::<Config>()
+        .cloned()
+        .ok_or_else(|| ApiError::internal("Missing config extension"))?;
 
-    let session_id = Uuid::from_str(cookie.value()).map_err(|_| StatusCode::UNAUTHORIZED)?;
+    // scripts send a personal API token instead of the session cookie
+    if let Some(token) = bearer_token(req.headers()) {
+        let mut conn = pool.get()?;
+        let (api_token, user) = api_tokens::authenticate(&mut conn, &token)?;
+        api_tokens::check_scope(&api_token, is_safe(req.method()))?;
+        drop(conn);
+
+        req.extensions_mut().insert(api_token);
+        req.extensions_mut().insert(user);
+        return Ok(next.run(req).await);
+    }
 
-    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let user = try_get_session(&mut conn, session_id).map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let (session_id, old_key) = session_id_from_cookies(req.headers(), &config.cookies)
+        .ok_or_else(ApiError::unauthorized)?;
 
+    let mut conn = pool.get()?;
+    let (session, user) = try_get_session(&mut conn, session_id, &config.session)?;
+
+    // everything an admin does as another user is on record before it happens
+    if let Some(impersonator) = session.impersonator_id {
+        let mut parts = RequestParts::new(req);
+        let ClientIp(ip) = parts.extract().await?;
+        req = parts.try_into_request().map_err(ApiError::internal)?;
+        // nested routers only see the rest of the path
+        let path = match req.extensions().get::<OriginalUri>() {
+            Some(OriginalUri(uri)) => uri.path(),
+            None => req.uri().path(),
+        };
+        audit::record(
+            &mut conn,
+            NewAuditEntry {
+                event: audit::IMPERSONATED_REQUEST,
+                user_id: Some(impersonator),
+                ip: Some(ip.to_string()),
+                details: format!("{} {} as {}", req.method(), path, user.login),
+            },
+        )
+        .map_err(|e| ApiError::internal(format!("{e:#}")))?;
+    }
+    // the handler takes its own connection
+    drop(conn);
+
+    let remember = session.remember;
+    req.extensions_mut().insert(session);
     req.extensions_mut().insert(user);
+    let mut res = next.run(req).await;
+
+    // sign the cookie again, so that the old key can be retired
+    if old_key && !res.headers().contains_key(http::header::SET_COOKIE) {
+        let mut jar = cookie::CookieJar::new();
+        jar.signed_mut(&config.cookies.key)
+            .add(session_cookie(session_id, remember, &config));
+        for cookie in jar.delta() {
+            if let Ok(value) = HeaderValue::from_str(&cookie.encoded().to_string()) {
+                res.headers_mut().append(http::header::SET_COOKIE, value);
+            }
+        }
+    }
+    Ok(res)
+}
+
+pub(super) fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(http::header::AUTHORIZATION)?.to_str().ok()?;
+    value
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim().to_string())
+}
+
+/// Keeps API tokens away from account management, has to run after [`middleware`]
+async fn require_session<B>(req: Request<B>, next: Next<B>) -> Result<Response, ApiError> {
+    if req.extensions().get::<Session>().is_none() {
+        return Err(AuthError::SessionRequired.into());
+    }
+    Ok(next.run(req).await)
+}
+
+/// Keeps admins acting as another user away from the user's security settings
+async fn forbid_impersonation<B>(req: Request<B>, next: Next<B>) -> Result<Response, ApiError> {
+    let impersonating = req
+        .extensions()
+        .get::<Session>()
+        .is_some_and(|session| session.impersonator_id.is_some());
+    if impersonating {
+        return Err(AuthError::ImpersonationForbidden.into());
+    }
+    Ok(next.run(req).await)
+}
+
+/// Finds the session cookie among all cookies of the request and checks its signature,
+/// returns the session id and whether it was signed with one of the old keys
+fn session_id_from_cookies(headers: &HeaderMap, config: &CookieConfig) -> Option<(Uuid, bool)> {
+    request_cookies(headers)
+        .filter(|cookie| cookie.name() == SESSION_COOKIE)
+        .find_map(|cookie| {
+            let (cookie, old_key) = verify_signed(cookie, config)?;
+            let session_id = Uuid::from_str(cookie.value()).ok()?;
+            Some((session_id, old_key))
+        })
+}
+
+/// Keeps users whose role requires two-factor authentication out until they enable it,
+/// has to run after [`middleware`]
+pub async fn two_factor_policy<B>(req: Request<B>, next: Next<B>) -> Result<Response, ApiError> {
+    let config = req
+        .extensions()
+        .get::<Config>()
+        .ok_or_else(|| ApiError::internal("Missing config extension"))?;
+    let user = req
+        .extensions()
+        .get::<User>()
+        .ok_or_else(|| ApiError::internal("Missing user extension"))?;
+
+    if two_factor::is_required(user, &config.two_factor) && user.totp_enabled_at.is_none() {
+        println!(
+            "User {} has to enable two-factor authentication",
+            user.login
+        );
+        return Err(AuthError::TwoFactorRequired.into());
+    }
     Ok(next.run(req).await)
 }
 
 async fn greet(Extension(current_user): Extension<User>) -> Html<String> {
     Html(format!("Hello {}", current_user.login))
 }
+
+pub(super) fn removal_cookie() -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, "").path("/").finish()
+}
+
+async fn post_logout(
+    pool: Extension<PgPool>,
+    Extension(session): Extension<Session>,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, StatusCode), ApiError> {
+    let mut conn = pool.get()?;
+
+    delete_session(&mut conn, session.id).map_err(ApiError::internal)?;
+    Ok((jar.remove(removal_cookie()), StatusCode::NO_CONTENT))
+}
+
+async fn post_logout_all(
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, StatusCode), ApiError> {
+    let mut conn = pool.get()?;
+
+    let count = delete_user_sessions(&mut conn, user.id).map_err(ApiError::internal)?;
+    println!("Deleted {} sessions of user {}", count, user.login);
+    Ok((jar.remove(removal_cookie()), StatusCode::NO_CONTENT))
+}
+
+#[derive(Serialize)]
+struct ActiveSession {
+    #[serde(flatten)]
+    session: Session,
+    current: bool,
+    /// Login of the system admin acting as the user in this session
+    impersonator: Option<String>,
+}
+
+async fn get_sessions(
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+    Extension(user): Extension<User>,
+    Extension(current): Extension<Session>,
+) -> Result<Json<Vec<ActiveSession>>, ApiError> {
+    let mut conn = pool.get()?;
+
+    let sessions =
+        get_user_sessions(&mut conn, user.id, &config.session).map_err(ApiError::internal)?;
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|(session, impersonator)| ActiveSession {
+                current: session.id == current.id,
+                session,
+                impersonator,
+            })
+            .collect(),
+    ))
+}
+
+async fn delete_user_session(
+    extract::Path(session_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+    Extension(current): Extension<Session>,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, StatusCode), ApiError> {
+    let mut conn = pool.get()?;
+
+    if !revoke_user_session(&mut conn, user.id, session_id).map_err(ApiError::internal)? {
+        return Err(ApiError::not_found());
+    }
+
+    if session_id == current.id {
+        return Ok((jar.remove(removal_cookie()), StatusCode::NO_CONTENT));
+    }
+    Ok((jar, StatusCode::NO_CONTENT))
+}
+
+async fn post_two_factor_setup(
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Enrollment>, ApiError> {
+    let mut conn = pool.get()?;
+
+    let enrollment = two_factor::begin_enrollment(&mut conn, &user, &config.two_factor)?;
+    Ok(Json(enrollment))
+}
+
+#[derive(Deserialize)]
+struct TwoFactorCode {
+    code: String,
+}
+
+#[derive(Serialize)]
+struct RecoveryCodes {
+    recovery_codes: Vec<String>,
+}
+
+async fn post_two_factor_enable(
+    extract::Json(payload): extract::Json<TwoFactorCode>,
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+    Extension(user): Extension<User>,
+) -> Result<Json<RecoveryCodes>, ApiError> {
+    let mut conn = pool.get()?;
+
+    let recovery_codes =
+        two_factor::confirm_enrollment(&mut conn, &user, &payload.code, &config.two_factor)?;
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+async fn post_two_factor_disable(
+    extract::Json(payload): extract::Json<TwoFactorCode>,
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+    Extension(user): Extension<User>,
+) -> Result<StatusCode, ApiError> {
+    let mut conn = pool.get()?;
+
+    two_factor::disable(&mut conn, &user, &payload.code, &config.two_factor)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_api_tokens(
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<ApiToken>>, ApiError> {
+    let mut conn = pool.get()?;
+
+    let tokens = api_tokens::list_tokens(&mut conn, user.id).map_err(ApiError::internal)?;
+    Ok(Json(tokens))
+}
+
+#[derive(Deserialize)]
+struct CreateApiToken {
+    name: String,
+    scopes: Vec<Scope>,
+    /// The token never expires when missing
+    expires_in_days: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct CreatedApiToken {
+    #[serde(flatten)]
+    api_token: ApiToken,
+    /// Shown only once
+    token: String,
+}
+
+async fn post_create_api_token(
+    extract::Json(payload): extract::Json<CreateApiToken>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<(StatusCode, Json<CreatedApiToken>), ApiError> {
+    let mut conn = pool.get()?;
+
+    if payload.scopes.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_scopes",
+            "At least one scope is required",
+        )
+        .with_field("scopes", "Use read, write or both"));
+    }
+    let expires = match payload.expires_in_days {
+        Some(days) => {
+            let lifetime = (1..=api_tokens::MAX_DAYS)
+                .contains(&days)
+                .then(|| std::time::Duration::from_secs(days as u64 * 24 * 60 * 60));
+            match lifetime.and_then(|lifetime| SystemTime::now().checked_add(lifetime)) {
+                Some(expires) => Some(expires),
+                None => {
+                    return Err(ApiError::new(
+                        StatusCode::BAD_REQUEST,
+                        "invalid_expiry",
+                        "Expiry has to be in the future and at most ten years away",
+                    )
+                    .with_field(
+                        "expires_in_days",
+                        format!("Use between 1 and {} days", api_tokens::MAX_DAYS),
+                    ))
+                }
+            }
+        }
+        None => None,
+    };
+
+    let (api_token, token) =
+        api_tokens::create_token(&mut conn, user.id, &payload.name, &payload.scopes, expires)
+            .map_err(ApiError::internal)?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedApiToken { api_token, token }),
+    ))
+}
+
+async fn delete_api_token(
+    extract::Path(token_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<StatusCode, ApiError> {
+    let mut conn = pool.get()?;
+
+    if !api_tokens::revoke_token(&mut conn, user.id, token_id).map_err(ApiError::internal)? {
+        return Err(ApiError::not_found());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
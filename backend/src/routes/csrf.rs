@@ -0,0 +1,83 @@
+use super::{
+    auth::{bearer_token, SESSION_COOKIE},
+    constant_time_eq, is_safe, request_cookies, verify_signed,
+};
+use crate::{auth::random_token, config::Config, errors::ApiError};
+use axum::{
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::Response,
+    Extension, Json,
+};
+use axum_extra::extract::{cookie::Cookie, SignedCookieJar};
+use serde::Serialize;
+
+/// Header which has to repeat the token from [`get_csrf_token`]
+pub const CSRF_HEADER: &str = "x-csrf-token";
+const CSRF_COOKIE: &str = "csrf_token";
+
+#[derive(Serialize)]
+pub struct CsrfToken {
+    token: String,
+}
+
+/// Hands out the token of the signed `csrf_token` cookie, setting the cookie first if needed
+pub async fn get_csrf_token(
+    config: Extension<Config>,
+    jar: SignedCookieJar,
+) -> (SignedCookieJar, Json<CsrfToken>) {
+    if let Some(cookie) = jar.get(CSRF_COOKIE) {
+        let token = cookie.value().to_string();
+        return (jar, Json(CsrfToken { token }));
+    }
+
+    let token = random_token();
+    let cookie = Cookie::build(CSRF_COOKIE, token.clone())
+        .path("/")
+        .http_only(true)
+        .secure(config.cookies.secure)
+        .same_site(config.cookies.same_site)
+        .finish();
+    (jar.add(cookie), Json(CsrfToken { token }))
+}
+
+/// Double-submit check: unsafe requests which carry the session cookie have to send
+/// the token of the `csrf_token` cookie in the `X-CSRF-Token` header as well.
+/// Other sites can make the browser send cookies, but can't read them or set headers.
+pub async fn middleware<B>(req: Request<B>, next: Next<B>) -> Result<Response, ApiError> {
+    let safe = is_safe(req.method());
+    // API token clients don't use cookies, so their requests can't be forged. Only a
+    // bearer token replaces the session cookie, other schemes leave it in charge.
+    let has_session = request_cookies(req.headers()).any(|cookie| cookie.name() == SESSION_COOKIE);
+    if safe || !has_session || bearer_token(req.headers()).is_some() {
+        return Ok(next.run(req).await);
+    }
+
+    let config = req
+        .extensions()
+        .get::<Config>()
+        .ok_or_else(|| ApiError::internal("Missing config extension"))?;
+
+    let expected = request_cookies(req.headers())
+        .filter(|cookie| cookie.name() == CSRF_COOKIE)
+        .find_map(|cookie| verify_signed(cookie, &config.cookies))
+        .map(|(cookie, _)| cookie.value().to_string());
+    let given = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    match (expected, given) {
+        (Some(expected), Some(given)) if constant_time_eq(&expected, given) => {
+            Ok(next.run(req).await)
+        }
+        _ => {
+            println!("Rejected request without a valid CSRF token");
+            Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "invalid_csrf_token",
+                "Missing or invalid CSRF token",
+            ))
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Drop-in replacements for axum's `Json`, `Path` and `Query` extractors which reject
+//! malformed requests with an [`ApiError`] instead of a plain text body
+
+use crate::errors::ApiError;
+use axum::{
+    async_trait,
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequest, RequestParts,
+    },
+};
+
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, B> FromRequest<B> for Json<T>
+where
+    axum::Json<T>: FromRequest<B, Rejection = JsonRejection>,
+    B: Send,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::<T>::from_request(req).await?;
+        Ok(Json(value))
+    }
+}
+
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, B> FromRequest<B> for Path<T>
+where
+    axum::extract::Path<T>: FromRequest<B, Rejection = PathRejection>,
+    B: Send,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) = axum::extract::Path::<T>::from_request(req).await?;
+        Ok(Path(value))
+    }
+}
+
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, B> FromRequest<B> for Query<T>
+where
+    axum::extract::Query<T>: FromRequest<B, Rejection = QueryRejection>,
+    B: Send,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) = axum::extract::Query::<T>::from_request(req).await?;
+        Ok(Query(value))
+    }
+}
@@ -0,0 +1,64 @@
+use super::extract;
+use crate::{
+    administration,
+    auth::AuthError,
+    database::PgPool,
+    errors::ApiError,
+    guardians,
+    models::{Grade, Relationship, Student, User},
+};
+use axum::{routing::get, Extension, Json, Router};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Read access for guardians to the records of their children. The client switches
+/// between children by picking another id from `/children`.
+pub fn router() -> Router {
+    Router::new()
+        .route("/children", get(get_children))
+        .route("/children/:student_id/grades", get(get_child_grades))
+        .route_layer(axum::middleware::from_fn(super::auth::two_factor_policy))
+        .route_layer(axum::middleware::from_fn(super::auth::middleware))
+}
+
+#[derive(Serialize)]
+struct Child {
+    #[serde(flatten)]
+    student: Student,
+    relationship: Relationship,
+    primary_contact: bool,
+    emergency_contact: bool,
+}
+
+async fn get_children(
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Child>>, ApiError> {
+    let mut conn = pool.get()?;
+
+    let children = guardians::list_children(&mut conn, user.id)?
+        .into_iter()
+        .map(|(student, guardian)| Child {
+            student,
+            relationship: guardian.relationship,
+            primary_contact: guardian.primary_contact,
+            emergency_contact: guardian.emergency_contact,
+        })
+        .collect();
+    Ok(Json(children))
+}
+
+async fn get_child_grades(
+    extract::Path(student_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Grade>>, ApiError> {
+    let mut conn = pool.get()?;
+    if !guardians::is_guardian(&mut conn, user.id, student_id)? {
+        println!("User {} is not a guardian of {}", user.login, student_id);
+        return Err(AuthError::Forbidden.into());
+    }
+
+    let grades = administration::list_grades(&mut conn, None, Some(student_id))?;
+    Ok(Json(grades))
+}
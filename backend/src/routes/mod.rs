@@ -1,2 +1,94 @@
+pub mod account;
 pub mod admin;
 pub mod auth;
+pub mod csrf;
+pub mod extract;
+pub mod guardian;
+pub mod oidc;
+pub mod summaries;
+
+use crate::{
+    config::{Config, CookieConfig},
+    errors::ApiError,
+};
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequest, RequestParts},
+    http::{header, HeaderMap, Method},
+};
+use axum_extra::extract::cookie::Cookie;
+use std::net::{IpAddr, SocketAddr};
+
+/// Whether a request with this method only reads data
+pub fn is_safe(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// Every cookie of the request, including ones sent in several `Cookie` headers
+pub fn request_cookies(headers: &HeaderMap) -> impl Iterator<Item = Cookie<'static>> + '_ {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| Cookie::parse_encoded(cookie.trim().to_owned()).ok())
+}
+
+/// Checks the signature of a cookie with the current key, then with the old ones.
+/// Returns the plain cookie and whether it was signed with an old key.
+pub fn verify_signed(
+    cookie: Cookie<'static>,
+    config: &CookieConfig,
+) -> Option<(Cookie<'static>, bool)> {
+    let jar = cookie::CookieJar::new();
+    std::iter::once(&config.key)
+        .chain(&config.old_keys)
+        .enumerate()
+        .find_map(|(i, key)| Some((jar.signed(key).verify(cookie.clone())?, i > 0)))
+}
+
+/// Address of the client. Behind a reverse proxy it's the last `X-Forwarded-For` entry,
+/// the one appended by the proxy itself.
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for ClientIp {
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let config = req
+            .extensions()
+            .get::<Config>()
+            .ok_or_else(|| ApiError::internal("Missing config extension"))?;
+
+        if config.trust_proxy {
+            let forwarded = req
+                .headers()
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .and_then(|ip| ip.trim().parse().ok());
+            if let Some(ip) = forwarded {
+                return Ok(ClientIp(ip));
+            }
+        }
+
+        let ConnectInfo(addr) = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .ok_or_else(|| ApiError::internal("Missing connection info"))?;
+        Ok(ClientIp(addr.ip()))
+    }
+}
+
+/// Compares secrets without revealing how many leading bytes match
+pub(crate) fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
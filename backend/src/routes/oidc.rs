@@ -0,0 +1,121 @@
+use super::{auth::session_cookie, constant_time_eq, extract::Query};
+use crate::{
+    auth::{create_session, AuthError},
+    config::{Config, OidcConfig},
+    database::PgPool,
+    errors::ApiError,
+    oidc::{self, LoginRequest},
+    two_factor,
+};
+use axum::{response::Redirect, Extension};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    SignedCookieJar,
+};
+use serde::Deserialize;
+
+const LOGIN_COOKIE: &str = "oidc_login";
+const LOGIN_COOKIE_PATH: &str = "/api/auth/oidc";
+
+fn oidc_config(config: &Config) -> Result<&OidcConfig, ApiError> {
+    config.oidc.as_ref().ok_or_else(ApiError::not_found)
+}
+
+fn login_cookie(value: String, config: &Config) -> Cookie<'static> {
+    Cookie::build(LOGIN_COOKIE, value)
+        .path(LOGIN_COOKIE_PATH)
+        .http_only(true)
+        .secure(config.cookies.secure)
+        // the provider sends the user back with a cross-site navigation
+        .same_site(SameSite::Lax)
+        .finish()
+}
+
+#[derive(Deserialize)]
+pub struct StartLogin {
+    #[serde(default)]
+    remember: bool,
+}
+
+/// Sends the browser to the identity provider
+pub async fn get_login(
+    Query(query): Query<StartLogin>,
+    config: Extension<Config>,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, Redirect), ApiError> {
+    let oidc_config = oidc_config(&config)?;
+    let metadata = oidc::discover(oidc_config)
+        .await
+        .map_err(|e| ApiError::internal(format!("{e:#}")))?;
+
+    let request = LoginRequest::new(query.remember, oidc_config);
+    let url = oidc::authorization_url(&metadata, oidc_config, &request)
+        .map_err(|e| ApiError::internal(format!("{e:#}")))?;
+
+    let mut cookie = login_cookie(request.encode(), &config);
+    cookie.set_max_age(oidc_config.login_timeout);
+    Ok((jar.add(cookie), Redirect::to(&url)))
+}
+
+#[derive(Deserialize)]
+pub struct Callback {
+    code: Option<String>,
+    state: String,
+    /// Set by the provider when the user cancelled or was refused
+    error: Option<String>,
+}
+
+/// Where the provider sends the browser back with an authorization code
+pub async fn get_callback(
+    Query(query): Query<Callback>,
+    pool: Extension<PgPool>,
+    config: Extension<Config>,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, Redirect), ApiError> {
+    let oidc_config = oidc_config(&config)?;
+
+    // the state ties the callback to the browser which started the login
+    let request = jar
+        .get(LOGIN_COOKIE)
+        .and_then(|cookie| LoginRequest::decode(cookie.value()))
+        .filter(|request| constant_time_eq(&request.state, &query.state))
+        .ok_or(AuthError::ExternalLoginFailed)?;
+    let jar = jar.remove(login_cookie(String::new(), &config));
+
+    if let Some(error) = query.error {
+        println!("Identity provider returned an error: {error}");
+        return Err(AuthError::ExternalLoginFailed.into());
+    }
+    let code = query.code.ok_or(AuthError::ExternalLoginFailed)?;
+
+    let metadata = oidc::discover(oidc_config)
+        .await
+        .map_err(|e| ApiError::internal(format!("{e:#}")))?;
+    let claims = oidc::exchange_code(&metadata, oidc_config, &code, &request).await?;
+
+    let mut conn = pool.get()?;
+    let user = oidc::find_or_create_user(
+        &mut conn,
+        &metadata.issuer,
+        &claims,
+        oidc_config.provision,
+        &config.password,
+    )?;
+
+    if user.totp_enabled_at.is_some() {
+        let pending_login = two_factor::begin_login(&mut conn, user.id, request.remember, None)
+            .map_err(ApiError::internal)?;
+        let url = format!(
+            "{}/login/2fa?pending_login={}",
+            config.app_url, pending_login
+        );
+        return Ok((jar, Redirect::to(&url)));
+    }
+
+    let session_id =
+        create_session(&mut conn, user.id, request.remember).map_err(ApiError::internal)?;
+    Ok((
+        jar.add(session_cookie(session_id, request.remember, &config)),
+        Redirect::to(&config.app_url),
+    ))
+}
@@ -0,0 +1,79 @@
+use super::extract;
+use crate::{
+    administration,
+    auth::{authorize, AuthError},
+    database::PgPool,
+    errors::ApiError,
+    guardians,
+    models::{Role, User},
+    summaries::{self, ClassSummary, SubjectAverage},
+};
+use axum::{routing::get, Extension, Json, Router};
+use uuid::Uuid;
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/student/:student_id", get(get_student_summary))
+        .route("/class/:class_id", get(get_class_summary))
+        .route_layer(axum::middleware::from_fn(super::auth::two_factor_policy))
+        .route_layer(axum::middleware::from_fn(super::auth::middleware))
+}
+
+/// Averages for the student themselves, their guardians and the school's staff
+async fn get_student_summary(
+    extract::Path(student_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<SubjectAverage>>, ApiError> {
+    let mut conn = pool.get()?;
+    // only system admins may tell a missing student from someone else's
+    let student = match administration::get_student(&mut conn, student_id) {
+        Err(administration::Error::NotFound) if user.role != Role::SystemAdmin => {
+            println!(
+                "User {} asked for unknown student {}",
+                user.login, student_id
+            );
+            return Err(AuthError::Forbidden.into());
+        }
+        result => result?,
+    };
+    let own = student.user_id == Some(user.id);
+    if !own && !guardians::is_guardian(&mut conn, user.id, student.id)? {
+        authorize(
+            &user,
+            &[Role::SchoolAdmin, Role::Teacher],
+            Some(student.school_id),
+        )?;
+    }
+
+    let averages = summaries::student_averages(&mut conn, student.id)?;
+    Ok(Json(averages))
+}
+
+/// Summary for the class's teacher and the school's admins
+async fn get_class_summary(
+    extract::Path(class_id): extract::Path<Uuid>,
+    pool: Extension<PgPool>,
+    Extension(user): Extension<User>,
+) -> Result<Json<ClassSummary>, ApiError> {
+    let mut conn = pool.get()?;
+    let class = match administration::get_class(&mut conn, class_id) {
+        Err(administration::Error::NotFound) if user.role != Role::SystemAdmin => {
+            println!("User {} asked for unknown class {}", user.login, class_id);
+            return Err(AuthError::Forbidden.into());
+        }
+        result => result?,
+    };
+    let school = administration::school_of_class(&mut conn, class.id)?;
+    authorize(&user, &[Role::SchoolAdmin, Role::Teacher], Some(school))?;
+    if user.role == Role::Teacher {
+        let teacher = administration::get_teacher(&mut conn, class.teacher_id)?;
+        if teacher.user_id != Some(user.id) {
+            println!("User {} doesn't teach class {}", user.login, class.id);
+            return Err(AuthError::Forbidden.into());
+        }
+    }
+
+    let summary = summaries::class_summary(&mut conn, class.id)?;
+    Ok(Json(summary))
+}
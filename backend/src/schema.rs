@@ -1,5 +1,29 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Varchar,
+        token_hash -> Varchar,
+        scopes -> Array<Text>,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    audit_log (id) {
+        id -> Uuid,
+        event -> Varchar,
+        user_id -> Nullable<Uuid>,
+        ip -> Nullable<Varchar>,
+        details -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     class_students (class_id, student_id) {
         class_id -> Uuid,
@@ -17,13 +41,45 @@ diesel::table! {
 }
 
 diesel::table! {
-    grades (student_id, subject_id, task_id) {
-        value -> Float8,
+    grade_history (id) {
+        id -> Uuid,
+        grade_id -> Uuid,
+        student_id -> Uuid,
+        old_symbol -> Nullable<Varchar>,
+        new_symbol -> Nullable<Varchar>,
+        old_value -> Nullable<Float8>,
+        new_value -> Nullable<Float8>,
+        old_weight -> Nullable<Int4>,
+        new_weight -> Nullable<Int4>,
+        teacher_id -> Uuid,
+        changed_by -> Nullable<Uuid>,
+        reason -> Nullable<Varchar>,
+        changed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    grade_symbols (school_id, symbol) {
+        school_id -> Uuid,
+        symbol -> Varchar,
+        value -> Nullable<Float8>,
+        counts -> Bool,
+    }
+}
+
+diesel::table! {
+    grades (id) {
+        value -> Nullable<Float8>,
         weight -> Int4,
         task_id -> Uuid,
         student_id -> Uuid,
         subject_id -> Uuid,
         teacher_id -> Uuid,
+        symbol -> Varchar,
+        counts -> Bool,
+        id -> Uuid,
+        attempt -> Int4,
+        corrects_id -> Nullable<Uuid>,
     }
 }
 
@@ -35,12 +91,76 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    guardians (user_id, student_id) {
+        user_id -> Uuid,
+        student_id -> Uuid,
+        relationship -> Varchar,
+        primary_contact -> Bool,
+        emergency_contact -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    invitations (id) {
+        id -> Uuid,
+        code_hash -> Varchar,
+        student_id -> Nullable<Uuid>,
+        teacher_id -> Nullable<Uuid>,
+        created_by -> Uuid,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        redeemed_at -> Nullable<Timestamp>,
+        redeemed_by -> Nullable<Uuid>,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    login_failures (id) {
+        id -> Uuid,
+        login -> Nullable<Varchar>,
+        ip -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    oidc_identities (issuer, subject) {
+        issuer -> Varchar,
+        subject -> Varchar,
+        user_id -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    pending_logins (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        remember -> Bool,
+        created_at -> Timestamp,
+        invitation_hash -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    recovery_codes (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        code_hash -> Varchar,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     schools (id) {
         id -> Uuid,
         name -> Varchar,
         place -> Varchar,
         school_type -> Nullable<Varchar>,
+        retake_policy -> Varchar,
     }
 }
 
@@ -49,6 +169,9 @@ diesel::table! {
         id -> Uuid,
         iat -> Timestamp,
         user_id -> Uuid,
+        last_seen -> Timestamp,
+        remember -> Bool,
+        impersonator_id -> Nullable<Uuid>,
     }
 }
 
@@ -76,6 +199,12 @@ diesel::table! {
     tasks (id) {
         id -> Uuid,
         name -> Varchar,
+        class_id -> Nullable<Uuid>,
+        category -> Varchar,
+        weight -> Int4,
+        date -> Nullable<Date>,
+        description -> Nullable<Varchar>,
+        max_points -> Nullable<Float8>,
     }
 }
 
@@ -84,48 +213,93 @@ diesel::table! {
         id -> Uuid,
         first_name -> Varchar,
         last_name -> Varchar,
-        user_id -> Uuid,
+        user_id -> Nullable<Uuid>,
         school_id -> Uuid,
     }
 }
 
+diesel::table! {
+    user_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        purpose -> Varchar,
+        token_hash -> Varchar,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Uuid,
         login -> Varchar,
         email -> Varchar,
         password -> Varchar,
+        role -> Varchar,
+        school_id -> Nullable<Uuid>,
+        verified_at -> Nullable<Timestamp>,
+        totp_secret -> Nullable<Varchar>,
+        totp_enabled_at -> Nullable<Timestamp>,
+        pending_email -> Nullable<Varchar>,
+        deleted_at -> Nullable<Timestamp>,
+        totp_last_step -> Nullable<Int8>,
     }
 }
 
+diesel::joinable!(api_tokens -> users (user_id));
+diesel::joinable!(audit_log -> users (user_id));
 diesel::joinable!(class_students -> classes (class_id));
 diesel::joinable!(class_students -> students (student_id));
 diesel::joinable!(classes -> groups (group_id));
 diesel::joinable!(classes -> subjects (subject_id));
 diesel::joinable!(classes -> teachers (teacher_id));
+diesel::joinable!(grade_history -> users (changed_by));
+diesel::joinable!(grade_symbols -> schools (school_id));
 diesel::joinable!(grades -> students (student_id));
 diesel::joinable!(grades -> subjects (subject_id));
 diesel::joinable!(grades -> tasks (task_id));
 diesel::joinable!(grades -> teachers (teacher_id));
 diesel::joinable!(groups -> schools (school_id));
+diesel::joinable!(guardians -> students (student_id));
+diesel::joinable!(guardians -> users (user_id));
+diesel::joinable!(invitations -> students (student_id));
+diesel::joinable!(invitations -> teachers (teacher_id));
+diesel::joinable!(oidc_identities -> users (user_id));
+diesel::joinable!(pending_logins -> users (user_id));
+diesel::joinable!(recovery_codes -> users (user_id));
 diesel::joinable!(sessions -> users (user_id));
 diesel::joinable!(students -> groups (group_id));
 diesel::joinable!(students -> schools (school_id));
 diesel::joinable!(students -> users (user_id));
 diesel::joinable!(subjects -> schools (school_id));
+diesel::joinable!(tasks -> classes (class_id));
 diesel::joinable!(teachers -> schools (school_id));
 diesel::joinable!(teachers -> users (user_id));
+diesel::joinable!(user_tokens -> users (user_id));
+diesel::joinable!(users -> schools (school_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    audit_log,
     class_students,
     classes,
+    grade_history,
+    grade_symbols,
     grades,
     groups,
+    guardians,
+    invitations,
+    login_failures,
+    oidc_identities,
+    pending_logins,
+    recovery_codes,
     schools,
     sessions,
     students,
     subjects,
     tasks,
     teachers,
+    user_tokens,
     users,
 );
@@ -0,0 +1,45 @@
+use crate::{auth, config::SessionConfig, database::PgPool};
+use tokio::{sync::watch, task::JoinHandle, time};
+
+/// Periodically deletes expired sessions until `shutdown` changes or its sender is dropped
+pub fn spawn(
+    pool: PgPool,
+    config: SessionConfig,
+    mut shutdown: watch::Receiver<()>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = time::interval(config.reaper_interval);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.changed() => break,
+            }
+
+            let pool = pool.clone();
+            match tokio::task::spawn_blocking(move || reap(&pool, &config)).await {
+                Ok(Ok(0)) => {}
+                Ok(Ok(count)) => tracing::info!("Deleted {count} expired sessions"),
+                Ok(Err(e)) => tracing::error!("Failed to delete expired sessions: {e:#}"),
+                Err(e) => tracing::error!("Session reaper panicked: {e}"),
+            }
+        }
+
+        tracing::info!("Session reaper stopped");
+    })
+}
+
+/// Deletes expired sessions batch by batch, returns how many were deleted
+fn reap(pool: &PgPool, config: &SessionConfig) -> anyhow::Result<usize> {
+    let mut conn = pool.get()?;
+    let mut total = 0;
+
+    loop {
+        let count = auth::delete_expired_sessions(&mut conn, config, config.reaper_batch_size)?;
+        total += count;
+        if (count as i64) < config.reaper_batch_size {
+            return Ok(total);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{
+    administration::{self, Error, PgConn},
+    models::RetakePolicy,
+    schema::{class_students, grades, subjects},
+};
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Weighted average of a student's grades in one subject
+#[derive(Serialize, Debug)]
+pub struct SubjectAverage {
+    pub subject_id: Uuid,
+    pub subject_name: String,
+    /// `None` while no grade counts towards it
+    pub average: Option<f64>,
+    /// Tasks with a grade which counts towards the average
+    pub grades: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StudentAverage {
+    pub student_id: Uuid,
+    pub average: Option<f64>,
+}
+
+/// How many grades with a symbol a class got
+#[derive(Serialize, Debug)]
+pub struct SymbolCount {
+    pub symbol: String,
+    pub count: usize,
+}
+
+/// Averages of the students of a class in the class's subject
+#[derive(Serialize, Debug)]
+pub struct ClassSummary {
+    pub class_id: Uuid,
+    pub subject_id: Uuid,
+    /// Mean of the students' averages, students without counted grades are left out
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    /// Counted grades by symbol including every attempt, lowest value first
+    pub distribution: Vec<SymbolCount>,
+    pub students: Vec<StudentAverage>,
+}
+
+/// Sum of values times weights divided by the sum of weights
+fn weighted_average(grades: &[(f64, i32)]) -> Option<f64> {
+    let weights: i64 = grades.iter().map(|(_, weight)| i64::from(*weight)).sum();
+    if weights <= 0 {
+        return None;
+    }
+    let sum: f64 = grades
+        .iter()
+        .map(|(value, weight)| value * f64::from(*weight))
+        .sum();
+    Some(sum / weights as f64)
+}
+
+/// Counted attempts at tasks, keyed by task
+type Attempts = BTreeMap<Uuid, Vec<Attempt>>;
+
+struct Attempt {
+    attempt: i32,
+    value: f64,
+    weight: i32,
+}
+
+/// Value and weight of the attempt at a task which counts under the policy
+fn counted_attempt(attempts: &[Attempt], policy: RetakePolicy) -> Option<(f64, i32)> {
+    let latest = attempts.iter().max_by_key(|attempt| attempt.attempt)?;
+    match policy {
+        RetakePolicy::Latest => Some((latest.value, latest.weight)),
+        RetakePolicy::Best => attempts
+            .iter()
+            .max_by(|a, b| a.value.total_cmp(&b.value))
+            .map(|best| (best.value, best.weight)),
+        RetakePolicy::Average => {
+            let values: Vec<f64> = attempts.iter().map(|attempt| attempt.value).collect();
+            mean(&values).map(|value| (value, latest.weight))
+        }
+    }
+}
+
+/// Weighted average over the tasks, one attempt per task as the policy decides
+fn average_of(tasks: &Attempts, policy: RetakePolicy) -> (Option<f64>, usize) {
+    let counted: Vec<(f64, i32)> = tasks
+        .values()
+        .filter_map(|attempts| counted_attempt(attempts, policy))
+        .collect();
+    (weighted_average(&counted), counted.len())
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+fn median(values: &[f64]) -> Option<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((sorted[middle - 1] + sorted[middle]) / 2.0),
+        _ => Some(sorted[middle]),
+    }
+}
+
+/// Per-subject averages of a student, marks which don't count are left out
+pub fn student_averages(
+    conn: &mut PgConn,
+    student_uuid: Uuid,
+) -> Result<Vec<SubjectAverage>, Error> {
+    let student = administration::get_student(conn, student_uuid)?;
+    let policy = administration::get_school(conn, student.school_id)?.retake_policy;
+    let rows = grades::table
+        .inner_join(subjects::table)
+        .filter(grades::student_id.eq(student.id))
+        .order(subjects::name)
+        .select((
+            subjects::id,
+            subjects::name,
+            grades::task_id,
+            grades::attempt,
+            grades::value,
+            grades::weight,
+            grades::counts,
+        ))
+        .load::<(Uuid, String, Uuid, i32, Option<f64>, i32, bool)>(conn)?;
+
+    let mut by_subject: BTreeMap<(String, Uuid), Attempts> = BTreeMap::new();
+    for (subject_id, subject_name, task_id, attempt, value, weight, counts) in rows {
+        let tasks = by_subject.entry((subject_name, subject_id)).or_default();
+        if let (Some(value), true) = (value, counts) {
+            tasks.entry(task_id).or_default().push(Attempt {
+                attempt,
+                value,
+                weight,
+            });
+        }
+    }
+
+    Ok(by_subject
+        .into_iter()
+        .map(|((subject_name, subject_id), tasks)| {
+            let (average, grades) = average_of(&tasks, policy);
+            SubjectAverage {
+                subject_id,
+                subject_name,
+                average,
+                grades,
+            }
+        })
+        .collect())
+}
+
+pub fn class_summary(conn: &mut PgConn, class_uuid: Uuid) -> Result<ClassSummary, Error> {
+    let class = administration::get_class(conn, class_uuid)?;
+    let school = administration::school_of_class(conn, class.id)?;
+    let policy = administration::get_school(conn, school)?.retake_policy;
+    let members = class_students::table
+        .filter(class_students::class_id.eq(class.id))
+        .select(class_students::student_id)
+        .load::<Uuid>(conn)?;
+    let rows = grades::table
+        .filter(grades::subject_id.eq(class.subject_id))
+        .filter(grades::student_id.eq_any(&members))
+        .filter(grades::counts)
+        .filter(grades::value.is_not_null())
+        .select((
+            grades::student_id,
+            grades::task_id,
+            grades::attempt,
+            grades::symbol,
+            grades::value,
+            grades::weight,
+        ))
+        .load::<(Uuid, Uuid, i32, String, Option<f64>, i32)>(conn)?;
+
+    let mut by_student: BTreeMap<Uuid, Attempts> = members
+        .iter()
+        .map(|student| (*student, Attempts::new()))
+        .collect();
+    let mut by_symbol: BTreeMap<String, (f64, usize)> = BTreeMap::new();
+    for (student_id, task_id, attempt, symbol, value, weight) in rows {
+        let value = value.unwrap_or_default();
+        by_student
+            .entry(student_id)
+            .or_default()
+            .entry(task_id)
+            .or_default()
+            .push(Attempt {
+                attempt,
+                value,
+                weight,
+            });
+        by_symbol.entry(symbol).or_insert((value, 0)).1 += 1;
+    }
+
+    let students: Vec<StudentAverage> = by_student
+        .into_iter()
+        .map(|(student_id, tasks)| StudentAverage {
+            student_id,
+            average: average_of(&tasks, policy).0,
+        })
+        .collect();
+    let averages: Vec<f64> = students
+        .iter()
+        .filter_map(|student| student.average)
+        .collect();
+
+    let mut distribution: Vec<(f64, SymbolCount)> = by_symbol
+        .into_iter()
+        .map(|(symbol, (value, count))| (value, SymbolCount { symbol, count }))
+        .collect();
+    distribution.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    Ok(ClassSummary {
+        class_id: class.id,
+        subject_id: class.subject_id,
+        mean: mean(&averages),
+        median: median(&averages),
+        distribution: distribution.into_iter().map(|(_, count)| count).collect(),
+        students,
+    })
+}
@@ -0,0 +1,325 @@
+use crate::{
+    auth::{hash_token, AuthError, PgConn},
+    config::TwoFactorConfig,
+    models::{NewRecoveryCode, PendingLogin, User},
+    routes::constant_time_eq,
+    schema::{pending_logins, recovery_codes, users},
+};
+use anyhow::Context;
+use diesel::{delete, insert_into, prelude::*, update};
+use qrcode::{render::svg, QrCode};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::Serialize;
+use std::time::SystemTime;
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const STEP_SECONDS: u64 = 30;
+const SKEW_STEPS: u64 = 1;
+
+/// Everything an authenticator app needs to start generating codes
+#[derive(Serialize)]
+pub struct Enrollment {
+    /// Base32 secret, for typing it in by hand
+    pub secret: String,
+    pub otpauth_uri: String,
+    /// `otpauth_uri` encoded as an SVG QR code
+    pub qr_svg: String,
+}
+
+/// RFC 6238 defaults: SHA-1, 6 digits, 30 second steps, one step of clock skew allowed
+fn totp(secret: &str, user: &User, config: &TwoFactorConfig) -> anyhow::Result<TOTP> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .context("Invalid TOTP secret")?;
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        SKEW_STEPS as u8,
+        STEP_SECONDS,
+        secret,
+        Some(config.issuer.clone()),
+        user.email.clone(),
+    )
+    .context("Failed to set up TOTP")
+}
+
+/// Accepts a TOTP code at most once. RFC 6238 §5.2 asks to refuse a code once one from
+/// the same time step was accepted, so the step of every accepted code is stored and
+/// only codes of later steps pass.
+fn accept_totp(
+    conn: &mut PgConnection,
+    user: &User,
+    secret: &str,
+    code: &str,
+    config: &TwoFactorConfig,
+) -> Result<bool, AuthError> {
+    let totp = totp(secret, user, config)?;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .context("System time is before the epoch")?
+        .as_secs()
+        / STEP_SECONDS;
+    let step = (now.saturating_sub(SKEW_STEPS)..=now + SKEW_STEPS)
+        .find(|step| constant_time_eq(&totp.generate(step * STEP_SECONDS), code));
+    let Some(step) = step else {
+        return Ok(false);
+    };
+
+    let step = step as i64;
+    let accepted = update(
+        users::table.filter(users::id.eq(user.id)).filter(
+            users::totp_last_step
+                .is_null()
+                .or(users::totp_last_step.lt(step)),
+        ),
+    )
+    .set(users::totp_last_step.eq(step))
+    .execute(conn)
+    .context("Failed to store TOTP step")?;
+    if accepted == 0 {
+        println!("User {} reused a TOTP code", user.login);
+    }
+    Ok(accepted > 0)
+}
+
+fn random_recovery_code() -> String {
+    let code: String = thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(10)
+        .map(|c| (c as char).to_ascii_lowercase())
+        .collect();
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// Whether the policy makes two-factor authentication mandatory for the user
+pub fn is_required(user: &User, config: &TwoFactorConfig) -> bool {
+    config.required_roles.contains(&user.role)
+}
+
+/// Generates a new secret, replacing the one of an unfinished enrollment.
+/// Two-factor authentication stays off until [`confirm_enrollment`].
+pub fn begin_enrollment(
+    conn: &mut PgConn,
+    user: &User,
+    config: &TwoFactorConfig,
+) -> Result<Enrollment, AuthError> {
+    if user.totp_enabled_at.is_some() {
+        return Err(AuthError::TwoFactorAlreadyEnabled);
+    }
+
+    let bytes: [u8; 20] = thread_rng().gen();
+    let secret = Secret::Raw(bytes.to_vec()).to_encoded().to_string();
+    let otpauth_uri = totp(&secret, user, config)?.get_url();
+    let qr_svg = QrCode::new(&otpauth_uri)
+        .context("Failed to encode QR code")?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+
+    update(users::table.filter(users::id.eq(user.id)))
+        .set((
+            users::totp_secret.eq(&secret),
+            users::totp_last_step.eq(None::<i64>),
+        ))
+        .execute(conn)
+        .context("Failed to store TOTP secret")?;
+
+    Ok(Enrollment {
+        secret,
+        otpauth_uri,
+        qr_svg,
+    })
+}
+
+/// Turns two-factor authentication on once the user proves their authenticator works,
+/// returns recovery codes to show them once
+pub fn confirm_enrollment(
+    conn: &mut PgConn,
+    user: &User,
+    code: &str,
+    config: &TwoFactorConfig,
+) -> Result<Vec<String>, AuthError> {
+    if user.totp_enabled_at.is_some() {
+        return Err(AuthError::TwoFactorAlreadyEnabled);
+    }
+    let secret = user
+        .totp_secret
+        .as_deref()
+        .ok_or(AuthError::TwoFactorNotEnabled)?;
+
+    conn.transaction(|conn| {
+        if !accept_totp(conn, user, secret, code.trim(), config)? {
+            println!("Wrong TOTP code!");
+            return Err(AuthError::InvalidTwoFactorCode);
+        }
+
+        update(users::table.filter(users::id.eq(user.id)))
+            .set(users::totp_enabled_at.eq(SystemTime::now()))
+            .execute(conn)
+            .context("Failed to enable two-factor authentication")?;
+
+        replace_recovery_codes(conn, user.id)
+    })
+}
+
+/// Turns two-factor authentication off, requires a valid code
+pub fn disable(
+    conn: &mut PgConn,
+    user: &User,
+    code: &str,
+    config: &TwoFactorConfig,
+) -> Result<(), AuthError> {
+    if user.totp_enabled_at.is_none() {
+        return Err(AuthError::TwoFactorNotEnabled);
+    }
+
+    conn.transaction(|conn| {
+        if !verify_code(conn, user, code, config)? {
+            return Err(AuthError::InvalidTwoFactorCode);
+        }
+
+        update(users::table.filter(users::id.eq(user.id)))
+            .set((
+                users::totp_secret.eq(None::<String>),
+                users::totp_enabled_at.eq(None::<SystemTime>),
+            ))
+            .execute(conn)
+            .context("Failed to disable two-factor authentication")?;
+
+        delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user.id)))
+            .execute(conn)
+            .context("Failed to delete recovery codes")?;
+
+        Ok(())
+    })
+}
+
+/// Replaces all recovery codes of the user, only their hashes are stored
+fn replace_recovery_codes(
+    conn: &mut PgConnection,
+    user_uuid: Uuid,
+) -> Result<Vec<String>, AuthError> {
+    delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_uuid)))
+        .execute(conn)
+        .context("Failed to delete recovery codes")?;
+
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| random_recovery_code())
+        .collect();
+    let rows: Vec<NewRecoveryCode> = codes
+        .iter()
+        .map(|code| NewRecoveryCode {
+            user_id: user_uuid,
+            code_hash: hash_token(code),
+        })
+        .collect();
+
+    insert_into(recovery_codes::table)
+        .values(&rows)
+        .execute(conn)
+        .context("Failed to insert recovery codes")?;
+
+    Ok(codes)
+}
+
+/// Checks a TOTP code, or uses up a recovery code
+fn verify_code(
+    conn: &mut PgConnection,
+    user: &User,
+    code: &str,
+    config: &TwoFactorConfig,
+) -> Result<bool, AuthError> {
+    let secret = user
+        .totp_secret
+        .as_deref()
+        .ok_or(AuthError::TwoFactorNotEnabled)?;
+    let code = code.trim().to_ascii_lowercase();
+
+    if accept_totp(conn, user, secret, &code, config)? {
+        return Ok(true);
+    }
+
+    let used = update(
+        recovery_codes::table
+            .filter(recovery_codes::user_id.eq(user.id))
+            .filter(recovery_codes::code_hash.eq(hash_token(&code)))
+            .filter(recovery_codes::used_at.is_null()),
+    )
+    .set(recovery_codes::used_at.eq(SystemTime::now()))
+    .execute(conn)
+    .context("Failed to use recovery code")?;
+
+    if used > 0 {
+        println!("User {} used a recovery code", user.login);
+    }
+    Ok(used > 0)
+}
+
+/// Remembers a login which passed the password check, it has to be finished with [`finish_login`]
+pub fn begin_login(
+    conn: &mut PgConn,
+    user_uuid: Uuid,
+    remember_me: bool,
+    invitation_hash: Option<&str>,
+) -> anyhow::Result<Uuid> {
+    delete(pending_logins::table.filter(pending_logins::user_id.eq(user_uuid)))
+        .execute(conn)
+        .context("Failed to delete older pending logins")?;
+
+    insert_into(pending_logins::table)
+        .values((
+            pending_logins::user_id.eq(user_uuid),
+            pending_logins::remember.eq(remember_me),
+            pending_logins::invitation_hash.eq(invitation_hash),
+        ))
+        .returning(pending_logins::id)
+        .get_result::<Uuid>(conn)
+        .context("Failed to create pending login")
+}
+
+/// Login of the account a pending login belongs to, so that wrong codes count towards its lockout
+pub fn pending_login_owner(conn: &mut PgConn, pending_id: Uuid) -> anyhow::Result<Option<String>> {
+    pending_logins::table
+        .inner_join(users::table)
+        .filter(pending_logins::id.eq(pending_id))
+        .select(users::login)
+        .first::<String>(conn)
+        .optional()
+        .context("Failed to fetch owner of pending login")
+}
+
+/// Finishes a pending login if the code is correct, a wrong code may be retried until it expires
+pub fn finish_login(
+    conn: &mut PgConn,
+    pending_id: Uuid,
+    code: &str,
+    config: &TwoFactorConfig,
+) -> Result<PendingLogin, AuthError> {
+    conn.transaction(|conn| {
+        let pending = pending_logins::table
+            .filter(pending_logins::id.eq(pending_id))
+            .filter(pending_logins::created_at.gt(SystemTime::now() - config.pending_timeout))
+            .first::<PendingLogin>(conn)
+            .optional()
+            .context("Failed to fetch pending login")?
+            .ok_or(AuthError::InvalidToken)?;
+
+        let user = users::table
+            .filter(users::id.eq(pending.user_id))
+            .first::<User>(conn)
+            .context("Failed to fetch user")?;
+
+        if !verify_code(conn, &user, code, config)? {
+            println!("Wrong second factor for user {}", user.login);
+            return Err(AuthError::InvalidTwoFactorCode);
+        }
+
+        delete(pending_logins::table.filter(pending_logins::id.eq(pending.id)))
+            .execute(conn)
+            .context("Failed to delete pending login")?;
+
+        Ok(pending)
+    })
+}
@@ -0,0 +1,139 @@
+use backend::{administration, auth};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use time::macros::date;
+use uuid::Uuid;
+mod tools;
+
+#[tokio::test]
+async fn email_change_needs_confirmation() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let (login, old_email) = tools::register(&client, addr).await;
+    tools::login_as(&client, addr, &login).await;
+    let csrf = tools::csrf_token(&client, addr).await;
+
+    let new_email = format!("{}@gmail.com", Uuid::new_v4());
+    let res = client
+        .patch(format!("http://{}/api/auth/profile", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "password": "wrong_pass12345", "email": new_email }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let new_login = format!("renamed_{}", Uuid::new_v4());
+    let res = client
+        .patch(format!("http://{}/api/auth/profile", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "password": tools::PASSWORD, "login": new_login, "email": new_email }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let profile: Value = res.json().await.unwrap();
+    assert_eq!(profile["login"], new_login);
+    assert_eq!(profile["email"], old_email);
+    assert_eq!(profile["pending_email"], new_email);
+
+    // the old address is told about the change
+    let notices = std::fs::read_dir("outbox")
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .filter(|mail| mail.contains(&format!("To: {old_email}\n")))
+        .filter(|mail| mail.contains(&new_email))
+        .count();
+    assert_eq!(notices, 1);
+
+    let res = client
+        .post(format!("http://{}/api/auth/confirm-email", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "token": tools::mailed_token(&new_email, "confirm-email") }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let profile: Value = client
+        .get(format!("http://{}/api/auth/profile", addr))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(profile["email"], new_email);
+    assert_eq!(profile["pending_email"], Value::Null);
+
+    // addresses of other accounts can't be taken
+    let (_, taken) = tools::register(&tools::client(), addr).await;
+    let res = client
+        .patch(format!("http://{}/api/auth/profile", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "password": tools::PASSWORD, "email": taken }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn deleted_account_is_anonymised() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+    let csrf = tools::csrf_token(&client, addr).await;
+
+    let mut conn = tools::conn();
+    let user = auth::get_by_login(&mut conn, &login).unwrap().unwrap();
+    let school = administration::create_school(&mut conn, "Kept", "Poznan", None).unwrap();
+    let group = administration::create_group(&mut conn, "3C", school.id).unwrap();
+    let student = administration::create_student(
+        &mut conn,
+        "Adam",
+        "Mazur",
+        date!(2009 - 09 - 01),
+        school.id,
+        group.id,
+        Some(user.id),
+    )
+    .unwrap();
+
+    let res = client
+        .delete(format!("http://{}/api/auth/profile", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "password": tools::PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = client
+        .get(format!("http://{}/api/auth/profile", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = tools::client()
+        .post(format!("http://{}/api/auth/login", addr))
+        .json(&json!({ "login": login, "password": tools::PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // the school record still points at the anonymised row
+    let student = administration::get_student(&mut conn, student.id).unwrap();
+    assert_eq!(student.user_id, Some(user.id));
+    assert!(auth::get_by_login(&mut conn, &login).unwrap().is_none());
+    let deleted = auth::get_by_email(
+        &mut conn,
+        &format!("deleted_{}@deleted.invalid", user.id.simple()),
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(deleted.id, user.id);
+    assert!(deleted.deleted_at.is_some());
+}
@@ -0,0 +1,712 @@
+use backend::{administration, auth, models::Role};
+use reqwest::{Client, Response, StatusCode};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use time::macros::date;
+use uuid::Uuid;
+mod tools;
+
+/// Logs in a new system admin, returns the client and its CSRF token
+async fn system_admin(addr: SocketAddr) -> (Client, String) {
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+    tools::set_role(&login, Role::SystemAdmin, None);
+    let csrf = tools::csrf_token(&client, addr).await;
+    (client, csrf)
+}
+
+/// Asserts the status of a response and, for errors, its code
+async fn expect(res: Response, status: StatusCode, code: &str) {
+    assert_eq!(res.status(), status);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], code);
+}
+
+#[tokio::test]
+async fn admin_requires_login() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+
+    let res = client
+        .post(format!("http://{}/api/admin/school", addr))
+        .json(&json!({ "name": "School", "place": "Warsaw" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn admin_checks_role_and_school() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+    let csrf = tools::csrf_token(&client, addr).await;
+
+    let school = json!({ "name": "School", "place": "Warsaw" });
+    let res = client
+        .post(format!("http://{}/api/admin/school", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&school)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let own = administration::create_school(&mut tools::conn(), "Own", "Warsaw", None).unwrap();
+    let other = administration::create_school(&mut tools::conn(), "Other", "Cracow", None).unwrap();
+    tools::set_role(&login, Role::SchoolAdmin, Some(own.id));
+
+    let res = client
+        .post(format!("http://{}/api/admin/subject", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "name": "Math", "school_id": own.id }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let res = client
+        .post(format!("http://{}/api/admin/subject", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "name": "Math", "school_id": other.id }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "forbidden");
+
+    let res = client
+        .post(format!("http://{}/api/admin/school", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&school)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    tools::set_role(&login, Role::SystemAdmin, None);
+
+    let res = client
+        .post(format!("http://{}/api/admin/school", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&school)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let location = res.headers()["location"].to_str().unwrap().to_string();
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["name"], "School");
+    assert_eq!(
+        location,
+        format!("/api/admin/school/{}", body["id"].as_str().unwrap())
+    );
+
+    let res = client
+        .get(format!("http://{}{}", addr, location))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn malformed_requests_get_json_errors() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+    let school = administration::create_school(&mut tools::conn(), "Strict", "Nysa", None).unwrap();
+    tools::set_role(&login, Role::SchoolAdmin, Some(school.id));
+    let csrf = tools::csrf_token(&client, addr).await;
+
+    let res = client
+        .post(format!("http://{}/api/admin/subject", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "name": "Math" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_body");
+    assert_eq!(body["fields"][0]["field"], "school_id");
+
+    let res = client
+        .post(format!("http://{}/api/admin/subject", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .body("name=Math")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "unsupported_media_type");
+
+    let res = client
+        .post(format!("http://{}/api/admin/subject", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .header("content-type", "application/json")
+        .body("{\"name\":")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_json");
+
+    let res = client
+        .get(format!("http://{}/api/admin/school/not-a-uuid", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_path");
+
+    let res = client
+        .get(format!("http://{}/api/admin/grade?student_id=nobody", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_query");
+}
+
+#[tokio::test]
+async fn school_admins_assign_roles_only_within_their_school() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+    let csrf = tools::csrf_token(&client, addr).await;
+    let own = administration::create_school(&mut tools::conn(), "Own", "Radom", None).unwrap();
+    let other = administration::create_school(&mut tools::conn(), "Other", "Kielce", None).unwrap();
+    tools::set_role(&login, Role::SchoolAdmin, Some(own.id));
+
+    let user_id = |login: &str| {
+        auth::get_by_login(&mut tools::conn(), login)
+            .unwrap()
+            .unwrap()
+            .id
+    };
+    let assign = |target: uuid::Uuid| {
+        client
+            .post(format!("http://{}/api/admin/role", addr))
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({ "user_id": target, "role": "teacher", "school_id": own.id }))
+            .send()
+    };
+
+    let (system_admin, _) = tools::register(&tools::client(), addr).await;
+    tools::set_role(&system_admin, Role::SystemAdmin, None);
+    let res = assign(user_id(&system_admin)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let (stranger, _) = tools::register(&tools::client(), addr).await;
+    tools::set_role(&stranger, Role::Teacher, Some(other.id));
+    let res = assign(user_id(&stranger)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let stranger = auth::get_by_login(&mut tools::conn(), &stranger)
+        .unwrap()
+        .unwrap();
+    assert_eq!(stranger.school_id, Some(other.id));
+
+    let (newcomer, _) = tools::register(&tools::client(), addr).await;
+    let res = assign(user_id(&newcomer)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = assign(user_id(&newcomer)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn school_crud() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+    let csrf = tools::csrf_token(&client, addr).await;
+    tools::set_role(&login, Role::SystemAdmin, None);
+
+    let school = administration::create_school(&mut tools::conn(), "Crud", "Gdansk", None).unwrap();
+    let url = format!("http://{}/api/admin/school/{}", addr, school.id);
+
+    let res = client.get(&url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["name"], "Crud");
+
+    let res = client
+        .patch(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "name": "Renamed", "school_type": "primary" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["name"], "Renamed");
+    assert_eq!(body["place"], "Gdansk");
+    assert_eq!(body["school_type"], "primary");
+
+    let subject = administration::create_subject(&mut tools::conn(), "Math", school.id).unwrap();
+
+    let res = client
+        .delete(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "foreign_key_violation");
+
+    let res = client
+        .delete(format!("http://{}/api/admin/subject/{}", addr, subject.id))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .delete(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client.get(&url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn teacher_crud() {
+    let addr = tools::spawn_app().await;
+    let (client, csrf) = system_admin(addr).await;
+
+    let mut conn = tools::conn();
+    let school = administration::create_school(&mut conn, "Staffed", "Radom", None).unwrap();
+    let (teacher_login, _) = tools::register(&tools::client(), addr).await;
+    let account = auth::get_by_login(&mut conn, &teacher_login)
+        .unwrap()
+        .unwrap();
+
+    let res = client
+        .post(format!("http://{}/api/admin/teacher", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({
+            "first_name": "Ewa",
+            "last_name": "Dab",
+            "school_id": school.id,
+            "user_id": account.id,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let url = format!(
+        "http://{}{}",
+        addr,
+        res.headers()["location"].to_str().unwrap()
+    );
+    let teacher: Value = res.json().await.unwrap();
+    assert_eq!(teacher["user_id"], account.id.to_string());
+
+    // an account belongs to one teacher at most
+    let res = client
+        .post(format!("http://{}/api/admin/teacher", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({
+            "first_name": "Ewa",
+            "last_name": "Dab",
+            "school_id": school.id,
+            "user_id": account.id,
+        }))
+        .send()
+        .await
+        .unwrap();
+    expect(res, StatusCode::CONFLICT, "already_exists").await;
+
+    // leaving the link out keeps it, null clears it
+    let res = client
+        .patch(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "last_name": "Buk" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["last_name"], "Buk");
+    assert_eq!(body["user_id"], account.id.to_string());
+    let res = client
+        .patch(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "user_id": null }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["user_id"], Value::Null);
+
+    let res = client
+        .delete(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = client.get(&url).send().await.unwrap();
+    expect(res, StatusCode::NOT_FOUND, "not_found").await;
+}
+
+#[tokio::test]
+async fn student_crud() {
+    let addr = tools::spawn_app().await;
+    let (client, csrf) = system_admin(addr).await;
+    let mut conn = tools::conn();
+    let school = administration::create_school(&mut conn, "Pupils", "Torun", None).unwrap();
+    let group = administration::create_group(&mut conn, "5C", school.id).unwrap();
+
+    let student = |group_id: Uuid| {
+        client
+            .post(format!("http://{}/api/admin/student", addr))
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({
+                "first_name": "Adam",
+                "last_name": "Lis",
+                "date_of_birth": date!(2012 - 09 - 01),
+                "school_id": school.id,
+                "group_id": group_id,
+            }))
+            .send()
+    };
+    let res = student(Uuid::new_v4()).await.unwrap();
+    expect(res, StatusCode::CONFLICT, "foreign_key_violation").await;
+    let res = student(group.id).await.unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let url = format!(
+        "http://{}{}",
+        addr,
+        res.headers()["location"].to_str().unwrap()
+    );
+    let body: Value = res.json().await.unwrap();
+    let student_id: Uuid = body["id"].as_str().unwrap().parse().unwrap();
+
+    let (account_login, _) = tools::register(&tools::client(), addr).await;
+    let account = auth::get_by_login(&mut conn, &account_login)
+        .unwrap()
+        .unwrap();
+    let res = client
+        .patch(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "first_name": "Adas", "user_id": account.id }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["first_name"], "Adas");
+    assert_eq!(body["user_id"], account.id.to_string());
+    let res = client
+        .patch(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "user_id": null }))
+        .send()
+        .await
+        .unwrap();
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["user_id"], Value::Null);
+
+    // students attending a class can't be deleted
+    let subject = administration::create_subject(&mut conn, "Music", school.id).unwrap();
+    let teacher = administration::create_teacher(&mut conn, "Jan", "Kos", None, school.id).unwrap();
+    let class = administration::create_class(&mut conn, subject.id, group.id, teacher.id).unwrap();
+    administration::add_student_to_class(&mut conn, student_id, class.id).unwrap();
+    let res = client
+        .delete(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    expect(res, StatusCode::CONFLICT, "foreign_key_violation").await;
+
+    let res = client
+        .delete(format!(
+            "http://{}/api/admin/class-student/{}/{}",
+            addr, class.id, student_id
+        ))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = client
+        .delete(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = client.get(&url).send().await.unwrap();
+    expect(res, StatusCode::NOT_FOUND, "not_found").await;
+}
+
+#[tokio::test]
+async fn subject_and_group_crud() {
+    let addr = tools::spawn_app().await;
+    let (client, csrf) = system_admin(addr).await;
+    let mut conn = tools::conn();
+    let school = administration::create_school(&mut conn, "Named", "Plock", None).unwrap();
+    let teacher = administration::create_teacher(&mut conn, "Ada", "Gil", None, school.id).unwrap();
+
+    for entity in ["subject", "group"] {
+        let create = |school_id: Uuid| {
+            client
+                .post(format!("http://{}/api/admin/{}", addr, entity))
+                .header(tools::CSRF_HEADER, &csrf)
+                .json(&json!({ "name": "First", "school_id": school_id }))
+                .send()
+        };
+        let res = create(Uuid::new_v4()).await.unwrap();
+        expect(res, StatusCode::CONFLICT, "foreign_key_violation").await;
+        let res = create(school.id).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let url = format!(
+            "http://{}{}",
+            addr,
+            res.headers()["location"].to_str().unwrap()
+        );
+        let body: Value = res.json().await.unwrap();
+        let id: Uuid = body["id"].as_str().unwrap().parse().unwrap();
+
+        let res = client
+            .patch(&url)
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({ "name": "Second" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = client
+            .patch(&url)
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({}))
+            .send()
+            .await
+            .unwrap();
+        expect(res, StatusCode::BAD_REQUEST, "nothing_to_update").await;
+        let body: Value = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert_eq!(body["name"], "Second");
+
+        // classes hold on to their subject and group
+        let (subject, group) = match entity {
+            "subject" => (
+                id,
+                administration::create_group(&mut conn, "1A", school.id)
+                    .unwrap()
+                    .id,
+            ),
+            _ => (
+                administration::create_subject(&mut conn, "Art", school.id)
+                    .unwrap()
+                    .id,
+                id,
+            ),
+        };
+        let class = administration::create_class(&mut conn, subject, group, teacher.id).unwrap();
+        let res = client
+            .delete(&url)
+            .header(tools::CSRF_HEADER, &csrf)
+            .send()
+            .await
+            .unwrap();
+        expect(res, StatusCode::CONFLICT, "foreign_key_violation").await;
+
+        administration::delete_class(&mut conn, class.id).unwrap();
+        let res = client
+            .delete(&url)
+            .header(tools::CSRF_HEADER, &csrf)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = client.get(&url).send().await.unwrap();
+        expect(res, StatusCode::NOT_FOUND, "not_found").await;
+    }
+}
+
+#[tokio::test]
+async fn class_crud() {
+    let addr = tools::spawn_app().await;
+    let (client, csrf) = system_admin(addr).await;
+    let mut conn = tools::conn();
+    let school = administration::create_school(&mut conn, "Classy", "Kalisz", None).unwrap();
+    let group = administration::create_group(&mut conn, "3A", school.id).unwrap();
+    let subject = administration::create_subject(&mut conn, "Physics", school.id).unwrap();
+    let teacher =
+        administration::create_teacher(&mut conn, "Lena", "Ryba", None, school.id).unwrap();
+    let substitute =
+        administration::create_teacher(&mut conn, "Olek", "Ryba", None, school.id).unwrap();
+    let student = administration::create_student(
+        &mut conn,
+        "Ida",
+        "Mis",
+        date!(2009 - 02 - 03),
+        school.id,
+        group.id,
+        None,
+    )
+    .unwrap();
+
+    let create = |teacher_id: Uuid| {
+        client
+            .post(format!("http://{}/api/admin/class", addr))
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({
+                "subject_id": subject.id,
+                "group_id": group.id,
+                "teacher_id": teacher_id,
+            }))
+            .send()
+    };
+    let res = create(Uuid::new_v4()).await.unwrap();
+    expect(res, StatusCode::NOT_FOUND, "not_found").await;
+    let res = create(teacher.id).await.unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let url = format!(
+        "http://{}{}",
+        addr,
+        res.headers()["location"].to_str().unwrap()
+    );
+    let class: Value = res.json().await.unwrap();
+
+    let res = client
+        .patch(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "teacher_id": substitute.id }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["teacher_id"], substitute.id.to_string());
+
+    // a student attends a class once
+    let attend = || {
+        client
+            .post(format!("http://{}/api/admin/class-student", addr))
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({ "class_id": class["id"], "student_id": student.id }))
+            .send()
+    };
+    assert_eq!(attend().await.unwrap().status(), StatusCode::CREATED);
+    let res = attend().await.unwrap();
+    expect(res, StatusCode::CONFLICT, "already_exists").await;
+
+    let res = client
+        .delete(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    expect(res, StatusCode::CONFLICT, "foreign_key_violation").await;
+
+    let attendance = format!(
+        "http://{}/api/admin/class-student/{}/{}",
+        addr,
+        class["id"].as_str().unwrap(),
+        student.id
+    );
+    let res = client
+        .delete(&attendance)
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = client.get(&attendance).send().await.unwrap();
+    expect(res, StatusCode::NOT_FOUND, "not_found").await;
+    let res = client
+        .delete(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = client.get(&url).send().await.unwrap();
+    expect(res, StatusCode::NOT_FOUND, "not_found").await;
+}
+
+#[tokio::test]
+async fn guardian_crud() {
+    let addr = tools::spawn_app().await;
+    let (client, csrf) = system_admin(addr).await;
+    let mut conn = tools::conn();
+    let school = administration::create_school(&mut conn, "Cared", "Lublin", None).unwrap();
+    let group = administration::create_group(&mut conn, "2C", school.id).unwrap();
+    let student = administration::create_student(
+        &mut conn,
+        "Ala",
+        "Wrobel",
+        date!(2013 - 04 - 05),
+        school.id,
+        group.id,
+        None,
+    )
+    .unwrap();
+    let (parent_login, _) = tools::register(&tools::client(), addr).await;
+    let parent = auth::get_by_login(&mut conn, &parent_login)
+        .unwrap()
+        .unwrap();
+
+    let create = |user_id: Uuid| {
+        client
+            .post(format!("http://{}/api/admin/guardian", addr))
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({
+                "user_id": user_id,
+                "student_id": student.id,
+                "relationship": "mother",
+            }))
+            .send()
+    };
+    let res = create(Uuid::new_v4()).await.unwrap();
+    expect(res, StatusCode::CONFLICT, "foreign_key_violation").await;
+    let res = create(parent.id).await.unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let url = format!(
+        "http://{}{}",
+        addr,
+        res.headers()["location"].to_str().unwrap()
+    );
+    let res = create(parent.id).await.unwrap();
+    expect(res, StatusCode::CONFLICT, "already_exists").await;
+
+    let res = client
+        .patch(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "relationship": "legal_guardian", "primary_contact": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = client.get(&url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(body["relationship"], "legal_guardian");
+    assert_eq!(body["primary_contact"], true);
+    assert_eq!(body["emergency_contact"], false);
+
+    let res = client
+        .delete(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = client.get(&url).send().await.unwrap();
+    expect(res, StatusCode::NOT_FOUND, "not_found").await;
+}
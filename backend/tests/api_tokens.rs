@@ -0,0 +1,154 @@
+use backend::{models::Role, schema::api_tokens};
+use diesel::prelude::*;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::{
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+mod tools;
+
+/// Creates an API token for the logged in `client`, returns the created token
+async fn create_token(client: &Client, addr: SocketAddr, scopes: Value) -> Value {
+    let csrf = tools::csrf_token(client, addr).await;
+    let res = client
+        .post(format!("http://{}/api/auth/tokens", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "name": "import script", "scopes": scopes, "expires_in_days": 30 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    res.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn bearer_token_acts_as_user() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+    tools::set_role(&login, Role::SystemAdmin, None);
+
+    let read = create_token(&client, addr, json!(["read"])).await;
+    let write = create_token(&client, addr, json!(["read", "write"])).await;
+    assert!(read["token"].as_str().unwrap().starts_with("bib_"));
+    assert!(read.get("token_hash").is_none());
+
+    // a script without any cookies
+    let script = Client::new();
+    let res = script
+        .get(format!("http://{}/api/auth/greet", addr))
+        .bearer_auth(read["token"].as_str().unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), format!("Hello {login}"));
+
+    let school = json!({ "name": "School", "place": "Warsaw" });
+    let res = script
+        .post(format!("http://{}/api/admin/school", addr))
+        .bearer_auth(read["token"].as_str().unwrap())
+        .json(&school)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "insufficient_scope");
+
+    let res = script
+        .post(format!("http://{}/api/admin/school", addr))
+        .bearer_auth(write["token"].as_str().unwrap())
+        .json(&school)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    // tokens can't manage tokens or sessions
+    let res = script
+        .get(format!("http://{}/api/auth/tokens", addr))
+        .bearer_auth(write["token"].as_str().unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "session_required");
+
+    let tokens: Value = client
+        .get(format!("http://{}/api/auth/tokens", addr))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(tokens.as_array().unwrap().len(), 2);
+    assert!(tokens[0]["last_used_at"].is_object());
+    assert!(tokens[0].get("token").is_none());
+}
+
+#[tokio::test]
+async fn expiry_has_to_be_within_limits() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    tools::register_and_login(&client, addr).await;
+    let csrf = tools::csrf_token(&client, addr).await;
+
+    for days in [json!(0), json!(-1), json!(3651), json!(i64::MAX)] {
+        let res = client
+            .post(format!("http://{}/api/auth/tokens", addr))
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({ "name": "script", "scopes": ["read"], "expires_in_days": days }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{days}");
+        let body: Value = res.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_expiry");
+        assert_eq!(body["fields"][0]["field"], "expires_in_days");
+    }
+}
+
+#[tokio::test]
+async fn revoked_and_expired_tokens_are_refused() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    tools::register_and_login(&client, addr).await;
+
+    let revoked = create_token(&client, addr, json!(["read"])).await;
+    let expired = create_token(&client, addr, json!(["read"])).await;
+
+    let csrf = tools::csrf_token(&client, addr).await;
+    let res = client
+        .delete(format!(
+            "http://{}/api/auth/tokens/{}",
+            addr,
+            revoked["id"].as_str().unwrap()
+        ))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let expired_id: uuid::Uuid = expired["id"].as_str().unwrap().parse().unwrap();
+    diesel::update(api_tokens::table.find(expired_id))
+        .set(api_tokens::expires_at.eq(SystemTime::now() - Duration::from_secs(60)))
+        .execute(&mut tools::conn())
+        .unwrap();
+
+    for token in [&revoked, &expired] {
+        let res = Client::new()
+            .get(format!("http://{}/api/auth/greet", addr))
+            .bearer_auth(token["token"].as_str().unwrap())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let body: Value = res.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_api_token");
+    }
+}
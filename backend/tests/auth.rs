@@ -8,9 +8,10 @@ async fn register_and_login() {
     let addr = tools::spawn_app().await;
     let client = tools::client();
 
+    let email = format!("{}@gmail.com", Uuid::new_v4());
     let payload = json!({
         "login": format!("test_user_{}", Uuid::new_v4().to_string()),
-        "email": format!("{}@gmail.com", Uuid::new_v4().to_string()),
+        "email": email,
         "password": "strong_pass12345",
     });
 
@@ -23,6 +24,7 @@ async fn register_and_login() {
 
     assert_eq!(res.status(), StatusCode::OK);
 
+    // unverified accounts can't log in
     let res = client
         .post(format!("http://{}/api/auth/login", addr))
         .json(&payload)
@@ -30,13 +32,348 @@ async fn register_and_login() {
         .await
         .unwrap();
 
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "email_not_verified");
+
+    let res = client
+        .post(format!("http://{}/api/auth/verify-email", addr))
+        .json(&json!({ "token": tools::mailed_token(&email, "verify-email") }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .post(format!("http://{}/api/auth/login", addr))
+        .json(&payload)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{}/api/auth/greet", addr))
+        .send()
+        .await
+        .unwrap();
+
     assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn register_weak_password() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+
+    let res = client
+        .post(format!("http://{}/api/auth/register", addr))
+        .json(&json!({
+            "login": format!("test_user_{}", Uuid::new_v4()),
+            "email": format!("{}@gmail.com", Uuid::new_v4()),
+            "password": "12345",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "weak_password");
+    assert_eq!(body["fields"][0]["field"], "password");
+}
+
+#[tokio::test]
+async fn logout() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    tools::register_and_login(&client, addr).await;
+    let csrf = tools::csrf_token(&client, addr).await;
+
+    let res = client
+        .get(format!("http://{}/api/auth/sessions", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let sessions: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(sessions.as_array().unwrap().len(), 1);
+    assert_eq!(sessions[0]["current"], true);
+
+    let res = client
+        .post(format!("http://{}/api/auth/logout", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = client
+        .get(format!("http://{}/api/auth/greet", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn revoke_sessions() {
+    let addr = tools::spawn_app().await;
+    let laptop = tools::client();
+    let login = tools::register_and_login(&laptop, addr).await;
+    let csrf = tools::csrf_token(&laptop, addr).await;
+    let school_pc = tools::client();
+    tools::login_as(&school_pc, addr, &login).await;
+    let phone = tools::client();
+    tools::login_as(&phone, addr, &login).await;
+
+    let sessions: serde_json::Value = laptop
+        .get(format!("http://{}/api/auth/sessions", addr))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(sessions.as_array().unwrap().len(), 3);
+
+    // the most recent session belongs to the phone
+    let res = laptop
+        .delete(format!(
+            "http://{}/api/auth/sessions/{}",
+            addr,
+            sessions[0]["id"].as_str().unwrap()
+        ))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = phone
+        .get(format!("http://{}/api/auth/greet", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = laptop
+        .post(format!("http://{}/api/auth/logout-all", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = school_pc
+        .get(format!("http://{}/api/auth/greet", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn idle_session_expires() {
+    use backend::schema::sessions;
+    use diesel::prelude::*;
+    use std::time::{Duration, SystemTime};
+
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+
+    let mut conn = tools::conn();
+    let user = backend::auth::get_by_login(&mut conn, &login)
+        .unwrap()
+        .unwrap();
+    diesel::update(sessions::table.filter(sessions::user_id.eq(user.id)))
+        .set(sessions::last_seen.eq(SystemTime::now() - Duration::from_secs(24 * 60 * 60)))
+        .execute(&mut conn)
+        .unwrap();
 
     let res = client
         .get(format!("http://{}/api/auth/greet", addr))
         .send()
         .await
         .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "session_expired");
+}
+
+#[tokio::test]
+async fn remember_me_sets_persistent_cookie() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+    let csrf = tools::csrf_token(&client, addr).await;
 
+    let res = client
+        .post(format!("http://{}/api/auth/login", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "login": login, "password": tools::PASSWORD, "remember": true }))
+        .send()
+        .await
+        .unwrap();
     assert_eq!(res.status(), StatusCode::OK);
+    let cookie = res.headers()["set-cookie"].to_str().unwrap();
+    assert!(cookie.contains("Max-Age="));
+}
+
+#[tokio::test]
+async fn reset_forgotten_password() {
+    use backend::{api_tokens, auth, models::Scope};
+
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+
+    let (login, email) = tools::register(&client, addr).await;
+
+    // unknown addresses get the same answer
+    let res = client
+        .post(format!("http://{}/api/auth/forgot-pass", addr))
+        .json(&json!({ "email": format!("{}@gmail.com", Uuid::new_v4()) }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .post(format!("http://{}/api/auth/forgot-pass", addr))
+        .json(&json!({ "email": email }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let mut conn = tools::conn();
+    let user = auth::get_by_login(&mut conn, &login).unwrap().unwrap();
+    let (_, api_token) =
+        api_tokens::create_token(&mut conn, user.id, "Backup", &[Scope::Read], None).unwrap();
+
+    let token = tools::mailed_token(&email, "reset-pass");
+    let new_pass = "another_strong_pass678";
+    let reset = json!({ "token": token, "new_pass": new_pass });
+    let res = client
+        .post(format!("http://{}/api/auth/reset-pass", addr))
+        .json(&reset)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .post(format!("http://{}/api/auth/login", addr))
+        .json(&json!({ "login": login, "password": new_pass }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // API tokens made before the reset stop working
+    let res = tools::client()
+        .get(format!("http://{}/api/auth/greet", addr))
+        .bearer_auth(&api_token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // tokens are single use
+    let csrf = tools::csrf_token(&client, addr).await;
+    let res = client
+        .post(format!("http://{}/api/auth/reset-pass", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&reset)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_token");
+}
+
+#[tokio::test]
+async fn csrf_token_required_with_session_cookie() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    tools::register_and_login(&client, addr).await;
+    let csrf = tools::csrf_token(&client, addr).await;
+    assert_eq!(tools::csrf_token(&client, addr).await, csrf);
+
+    for token in [None, Some("forged")] {
+        let mut req = client.post(format!("http://{}/api/auth/logout", addr));
+        if let Some(token) = token {
+            req = req.header(tools::CSRF_HEADER, token);
+        }
+        let res = req.send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_csrf_token");
+    }
+
+    // only a bearer token replaces the session cookie
+    let res = client
+        .post(format!("http://{}/api/auth/logout", addr))
+        .basic_auth("someone", Some("secret"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_csrf_token");
+
+    // the token of another client's cookie doesn't match
+    let other = tools::csrf_token(&tools::client(), addr).await;
+    let res = client
+        .post(format!("http://{}/api/auth/logout", addr))
+        .header(tools::CSRF_HEADER, &other)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = client
+        .post(format!("http://{}/api/auth/logout", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn login_upgrades_old_password_hash() {
+    use backend::{auth, config::PasswordConfig, schema::users};
+    use diesel::prelude::*;
+
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let (login, _) = tools::register(&client, addr).await;
+
+    // hash made with the library defaults and a short salt
+    let old_hash =
+        argon2::hash_encoded(tools::PASSWORD.as_bytes(), b"saltsalt", &Default::default()).unwrap();
+    let mut conn = tools::conn();
+    diesel::update(users::table.filter(users::login.eq(&login)))
+        .set(users::password.eq(&old_hash))
+        .execute(&mut conn)
+        .unwrap();
+
+    tools::login_as(&client, addr, &login).await;
+
+    let user = auth::get_by_login(&mut conn, &login).unwrap().unwrap();
+    let config = PasswordConfig::from_env();
+    assert!(user.password.starts_with(&format!(
+        "$argon2id$v=19$m={},t={},p={}$",
+        config.memory_kib, config.iterations, config.parallelism
+    )));
+
+    // the new hash is left alone at the next login
+    tools::login_as(&tools::client(), addr, &login).await;
+    let again = auth::get_by_login(&mut conn, &login).unwrap().unwrap();
+    assert_eq!(again.password, user.password);
 }
@@ -0,0 +1,21 @@
+use backend::config::SessionConfig;
+use std::{env, panic};
+
+#[test]
+fn session_reaper_settings_have_to_be_positive() {
+    for key in [
+        "SESSION_REAPER_INTERVAL_SECONDS",
+        "SESSION_REAPER_BATCH_SIZE",
+    ] {
+        env::set_var(key, "0");
+        let error = panic::catch_unwind(SessionConfig::from_env).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<String>().unwrap(),
+            &format!("Invalid {key} variable: has to be at least 1")
+        );
+        env::remove_var(key);
+    }
+
+    env::set_var("SESSION_REAPER_BATCH_SIZE", "1");
+    assert_eq!(SessionConfig::from_env().reaper_batch_size, 1);
+}
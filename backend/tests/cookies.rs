@@ -0,0 +1,74 @@
+use reqwest::{header, Client, StatusCode};
+use serde_json::json;
+use std::net::SocketAddr;
+mod tools;
+
+const KEY: &str = "11111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111";
+const NEW_KEY: &str = "22222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222222";
+
+/// Logs in without a cookie store and returns the signed session cookie
+async fn session_cookie(addr: SocketAddr, login: &str) -> String {
+    let res = Client::new()
+        .post(format!("http://{}/api/auth/login", addr))
+        .json(&json!({ "login": login, "password": tools::PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let set_cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+    assert!(set_cookie.contains("HttpOnly"));
+    assert!(set_cookie.contains("SameSite=Lax"));
+    set_cookie.split(';').next().unwrap().to_string()
+}
+
+async fn greet(addr: SocketAddr, cookies: &str) -> reqwest::Response {
+    Client::new()
+        .get(format!("http://{}/api/auth/greet", addr))
+        .header(header::COOKIE, cookies)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn session_cookie_is_signed() {
+    let addr = tools::spawn_app().await;
+    let (login, _) = tools::register(&tools::client(), addr).await;
+    let cookie = session_cookie(addr, &login).await;
+
+    let res = greet(addr, &format!("theme=dark; {cookie}; lang=pl")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // the session id alone, without a signature, is not enough
+    let value = cookie.split_once('=').unwrap().1;
+    let session_id = &value[value.len() - 36..];
+    let res = greet(addr, &format!("session_id={session_id}")).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn old_keys_are_accepted_and_replaced() {
+    std::env::set_var("COOKIE_KEY", KEY);
+    std::env::remove_var("COOKIE_OLD_KEYS");
+    let old_app = tools::spawn_app().await;
+    let (login, _) = tools::register(&tools::client(), old_app).await;
+    let cookie = session_cookie(old_app, &login).await;
+
+    std::env::set_var("COOKIE_KEY", NEW_KEY);
+    let unaware_app = tools::spawn_app().await;
+    std::env::set_var("COOKIE_OLD_KEYS", KEY);
+    let new_app = tools::spawn_app().await;
+
+    let res = greet(unaware_app, &cookie).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = greet(new_app, &cookie).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let set_cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+    let new_cookie = set_cookie.split(';').next().unwrap();
+    assert_ne!(new_cookie, cookie);
+
+    let res = greet(unaware_app, new_cookie).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
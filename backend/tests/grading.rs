@@ -0,0 +1,319 @@
+use backend::{administration, auth, models::Role};
+use diesel::{sql_query, RunQueryDsl};
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use time::macros::date;
+use uuid::Uuid;
+mod tools;
+
+/// Grade payload for a new student of the school, every call grades someone else
+fn grade_for(school: Uuid, symbol: &str) -> Value {
+    let mut conn = tools::conn();
+    let group = administration::create_group(&mut conn, "4D", school).unwrap();
+    let subject = administration::create_subject(&mut conn, "History", school).unwrap();
+    let teacher = administration::create_teacher(&mut conn, "Jan", "Bak", None, school).unwrap();
+    let class = administration::create_class(&mut conn, subject.id, group.id, teacher.id).unwrap();
+    let task = tools::create_task(class.id, "Essay");
+    let student = administration::create_student(
+        &mut conn,
+        "Zofia",
+        "Kruk",
+        date!(2011 - 11 - 11),
+        school,
+        group.id,
+        None,
+    )
+    .unwrap();
+    administration::add_student_to_class(&mut conn, student.id, class.id).unwrap();
+    json!({
+        "symbol": symbol,
+        "weight": 2,
+        "teacher_id": teacher.id,
+        "student_id": student.id,
+        "subject_id": subject.id,
+        "task_id": task.id,
+    })
+}
+
+async fn post_grade(
+    client: &Client,
+    addr: SocketAddr,
+    csrf: &str,
+    grade: &Value,
+) -> reqwest::Response {
+    client
+        .post(format!("http://{}/api/admin/grade", addr))
+        .header(tools::CSRF_HEADER, csrf)
+        .json(grade)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn grades_follow_the_school_scale() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+    let school = administration::create_school(&mut tools::conn(), "Scaled", "Torun", None)
+        .unwrap()
+        .id;
+    tools::set_role(&login, Role::SchoolAdmin, Some(school));
+    let csrf = tools::csrf_token(&client, addr).await;
+
+    for symbol in ["42", "-3", "6+", "0"] {
+        let res = post_grade(&client, addr, &csrf, &grade_for(school, symbol)).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: Value = res.json().await.unwrap();
+        assert_eq!(body["code"], "not_on_scale");
+    }
+
+    // the default Polish scale
+    let res = post_grade(&client, addr, &csrf, &grade_for(school, "4+")).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let grade: Value = res.json().await.unwrap();
+    assert_eq!(grade["value"], 4.5);
+    assert_eq!(grade["counts"], true);
+
+    let res = post_grade(&client, addr, &csrf, &grade_for(school, "np")).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let grade: Value = res.json().await.unwrap();
+    assert_eq!(grade["value"], Value::Null);
+    assert_eq!(grade["counts"], false);
+
+    // changing the symbol brings the value along
+    let url = format!(
+        "http://{}/api/admin/grade/{}",
+        addr,
+        grade["id"].as_str().unwrap()
+    );
+    let res = client
+        .patch(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "symbol": "2-", "reason": "Misread the essay" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let grade: Value = res.json().await.unwrap();
+    assert_eq!(grade["value"], 1.75);
+    assert_eq!(grade["counts"], true);
+
+    let scale_url = format!("http://{}/api/admin/school/{}/grading-scale", addr, school);
+    let res = client
+        .put(&scale_url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!([{ "symbol": "A", "value": null, "counts": true }]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_scale");
+
+    let res = client
+        .put(&scale_url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!([
+            { "symbol": "A", "value": 5.0, "counts": true },
+            { "symbol": "B", "value": 4.0, "counts": true },
+            { "symbol": "inc", "value": null, "counts": false },
+        ]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let scale: Value = client
+        .get(&scale_url)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(scale.as_array().unwrap().len(), 3);
+    assert_eq!(scale[0]["symbol"], "B");
+
+    let res = post_grade(&client, addr, &csrf, &grade_for(school, "4+")).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = post_grade(&client, addr, &csrf, &grade_for(school, "A")).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn retakes_count_as_the_school_decides() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+    let school = administration::create_school(&mut tools::conn(), "Retaken", "Opole", None)
+        .unwrap()
+        .id;
+    tools::set_role(&login, Role::SchoolAdmin, Some(school));
+    let csrf = tools::csrf_token(&client, addr).await;
+
+    let mut payload = grade_for(school, "2");
+    let res = post_grade(&client, addr, &csrf, &payload).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let first: Value = res.json().await.unwrap();
+    assert_eq!(first["attempt"], 1);
+    assert_eq!(first["corrects_id"], Value::Null);
+
+    let res = post_grade(&client, addr, &csrf, &payload).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    payload["symbol"] = json!("5");
+    payload["corrects_id"] = first["id"].clone();
+    let res = post_grade(&client, addr, &csrf, &payload).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let retake: Value = res.json().await.unwrap();
+    assert_eq!(retake["attempt"], 2);
+    assert_eq!(retake["corrects_id"], first["id"]);
+
+    // a correction has to be for the same student, subject and task
+    let mut other = grade_for(school, "4");
+    other["corrects_id"] = first["id"].clone();
+    let res = post_grade(&client, addr, &csrf, &other).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_correction");
+
+    let summary_url = format!(
+        "http://{}/api/summary/student/{}",
+        addr,
+        payload["student_id"].as_str().unwrap()
+    );
+    let school_url = format!("http://{}/api/admin/school/{}", addr, school);
+    for (policy, average) in [("latest", 5.0), ("best", 5.0), ("average", 3.5)] {
+        let res = client
+            .patch(&school_url)
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({ "retake_policy": policy }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let averages: Value = client
+            .get(&summary_url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(averages[0]["average"], average, "{policy}");
+        assert_eq!(averages[0]["grades"], 1);
+    }
+
+    // the first attempt stays while a correction points at it
+    let first_url = format!(
+        "http://{}/api/admin/grade/{}",
+        addr,
+        first["id"].as_str().unwrap()
+    );
+    let res = client
+        .delete(format!("{first_url}?reason=Duplicate"))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn grade_changes_are_kept_in_the_history() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+    let school = administration::create_school(&mut tools::conn(), "Recorded", "Plock", None)
+        .unwrap()
+        .id;
+    tools::set_role(&login, Role::SchoolAdmin, Some(school));
+    let user = auth::get_by_login(&mut tools::conn(), &login)
+        .unwrap()
+        .unwrap();
+    let csrf = tools::csrf_token(&client, addr).await;
+
+    let res = post_grade(&client, addr, &csrf, &grade_for(school, "3")).await;
+    let grade: Value = res.json().await.unwrap();
+    let url = format!(
+        "http://{}/api/admin/grade/{}",
+        addr,
+        grade["id"].as_str().unwrap()
+    );
+
+    let res = client
+        .patch(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "symbol": "4", "reason": " " }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "reason_required");
+
+    let res = client
+        .patch(&url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "symbol": "4", "weight": 3, "reason": "Recounted the points" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .delete(format!("{url}?reason=Wrong%20student"))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = client.get(&url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let res = client.get(format!("{url}/history")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let history: Value = res.json().await.unwrap();
+    let history = history.as_array().unwrap();
+    assert_eq!(history.len(), 3);
+    assert_eq!(history[0]["old_symbol"], Value::Null);
+    assert_eq!(history[0]["new_symbol"], "3");
+    assert_eq!(history[0]["reason"], Value::Null);
+    assert_eq!(history[1]["old_symbol"], "3");
+    assert_eq!(history[1]["new_symbol"], "4");
+    assert_eq!(history[1]["old_value"], 3.0);
+    assert_eq!(history[1]["new_value"], 4.0);
+    assert_eq!(history[1]["old_weight"], 2);
+    assert_eq!(history[1]["new_weight"], 3);
+    assert_eq!(history[1]["reason"], "Recounted the points");
+    assert_eq!(history[2]["old_symbol"], "4");
+    assert_eq!(history[2]["new_symbol"], Value::Null);
+    assert_eq!(history[2]["reason"], "Wrong student");
+    for entry in history {
+        assert_eq!(entry["changed_by"], user.id.to_string());
+    }
+
+    // nobody rewrites history, not even with direct access to the database
+    let mut conn = tools::conn();
+    let id = grade["id"].as_str().unwrap();
+    assert!(sql_query(format!(
+        "update grade_history set reason = 'Never happened' where grade_id = '{id}'"
+    ))
+    .execute(&mut conn)
+    .is_err());
+    assert!(
+        sql_query(format!("delete from grade_history where grade_id = '{id}'"))
+            .execute(&mut conn)
+            .is_err()
+    );
+
+    let other = tools::client();
+    let other_login = tools::register_and_login(&other, addr).await;
+    let elsewhere = administration::create_school(&mut conn, "Elsewhere", "Plock", None)
+        .unwrap()
+        .id;
+    tools::set_role(&other_login, Role::Teacher, Some(elsewhere));
+    let res = other.get(format!("{url}/history")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,159 @@
+use backend::{administration, auth, models::Role};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use time::macros::date;
+mod tools;
+
+#[tokio::test]
+async fn guardians_read_grades_of_their_children() {
+    let addr = tools::spawn_app().await;
+    let mut conn = tools::conn();
+    let school = administration::create_school(&mut conn, "Family", "Gdansk", None).unwrap();
+    let group = administration::create_group(&mut conn, "2B", school.id).unwrap();
+    let subject = administration::create_subject(&mut conn, "Math", school.id).unwrap();
+    let teacher = administration::create_teacher(&mut conn, "Ewa", "Lis", None, school.id).unwrap();
+    let class = administration::create_class(&mut conn, subject.id, group.id, teacher.id).unwrap();
+    let task = tools::create_task(class.id, "Test");
+    let mut children = Vec::new();
+    for (first_name, symbol) in [("Ola", "5"), ("Piotr", "3+"), ("Stranger", "1")] {
+        let student = administration::create_student(
+            &mut conn,
+            first_name,
+            "Wrona",
+            date!(2012 - 03 - 14),
+            school.id,
+            group.id,
+            None,
+        )
+        .unwrap();
+        administration::add_student_to_class(&mut conn, student.id, class.id).unwrap();
+        administration::create_grade(
+            &mut conn,
+            symbol,
+            Some(1),
+            teacher.id,
+            student.id,
+            Some(subject.id),
+            task.id,
+            None,
+            None,
+        )
+        .unwrap();
+        children.push(student);
+    }
+
+    let admin = tools::client();
+    let admin_login = tools::register_and_login(&admin, addr).await;
+    tools::set_role(&admin_login, Role::SchoolAdmin, Some(school.id));
+    let csrf = tools::csrf_token(&admin, addr).await;
+
+    let parent = tools::client();
+    let parent_login = tools::register_and_login(&parent, addr).await;
+    let parent_user = auth::get_by_login(&mut conn, &parent_login)
+        .unwrap()
+        .unwrap();
+    for (child, relationship) in [(&children[0], "mother"), (&children[1], "legal_guardian")] {
+        let res = admin
+            .post(format!("http://{}/api/admin/guardian", addr))
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({
+                "user_id": parent_user.id,
+                "student_id": child.id,
+                "relationship": relationship,
+                "primary_contact": true,
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+    }
+
+    // the account joins the school as a parent
+    let parent_user = auth::get_by_login(&mut conn, &parent_login)
+        .unwrap()
+        .unwrap();
+    assert_eq!(parent_user.role, Role::Parent);
+    assert_eq!(parent_user.school_id, Some(school.id));
+
+    let res = admin
+        .patch(format!(
+            "http://{}/api/admin/guardian/{}/{}",
+            addr, parent_user.id, children[1].id
+        ))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "emergency_contact": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let listed: Value = parent
+        .get(format!("http://{}/api/guardian/children", addr))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let listed = listed.as_array().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0]["first_name"], "Ola");
+    assert_eq!(listed[0]["relationship"], "mother");
+    assert_eq!(listed[1]["relationship"], "legal_guardian");
+    assert_eq!(listed[1]["emergency_contact"], true);
+
+    // switching children is a matter of asking for another one
+    for (child, value) in [(&children[0], 5.0), (&children[1], 3.5)] {
+        let grades: Value = parent
+            .get(format!(
+                "http://{}/api/guardian/children/{}/grades",
+                addr, child.id
+            ))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(grades.as_array().unwrap().len(), 1);
+        assert_eq!(grades[0]["value"], value);
+    }
+
+    let res = parent
+        .get(format!(
+            "http://{}/api/guardian/children/{}/grades",
+            addr, children[2].id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // guardians don't get the staff view of the school
+    let res = parent
+        .get(format!("http://{}/api/admin/grade", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = admin
+        .delete(format!(
+            "http://{}/api/admin/guardian/{}/{}",
+            addr, parent_user.id, children[0].id
+        ))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = parent
+        .get(format!(
+            "http://{}/api/guardian/children/{}/grades",
+            addr, children[0].id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
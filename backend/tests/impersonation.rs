@@ -0,0 +1,136 @@
+use backend::{auth, models::Role, schema::audit_log};
+use diesel::prelude::*;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use uuid::Uuid;
+mod tools;
+
+#[tokio::test]
+async fn admin_acts_as_user_on_record() {
+    let addr = tools::spawn_app().await;
+    let support = tools::client();
+    let admin_login = tools::register_and_login(&support, addr).await;
+    tools::set_role(&admin_login, Role::SystemAdmin, None);
+    let csrf = tools::csrf_token(&support, addr).await;
+
+    let parent = tools::client();
+    let login = tools::register_and_login(&parent, addr).await;
+    let mut conn = tools::conn();
+    let admin = auth::get_by_login(&mut conn, &admin_login)
+        .unwrap()
+        .unwrap();
+    let user = auth::get_by_login(&mut conn, &login).unwrap().unwrap();
+
+    // other admins can't be impersonated
+    let other_admin = tools::register_and_login(&tools::client(), addr).await;
+    tools::set_role(&other_admin, Role::SystemAdmin, None);
+    let other_admin = auth::get_by_login(&mut conn, &other_admin)
+        .unwrap()
+        .unwrap();
+    let res = support
+        .post(format!("http://{}/api/admin/impersonate", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "user_id": other_admin.id }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = support
+        .post(format!("http://{}/api/admin/impersonate", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "user_id": user.id }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = support
+        .get(format!("http://{}/api/auth/greet", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.text().await.unwrap(), format!("Hello {login}"));
+
+    // the account's security settings are off limits
+    let res = support
+        .post(format!("http://{}/api/auth/change-pass", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "login": login, "pass": tools::PASSWORD, "new_pass": "another_strong_pass678" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "impersonation_forbidden");
+
+    let res = support
+        .post(format!("http://{}/api/auth/2fa/setup", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // and so is logging the user out
+    let session = Uuid::new_v4();
+    let res = support
+        .post(format!("http://{}/api/auth/logout-all", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = support
+        .delete(format!("http://{}/api/auth/sessions/{}", addr, session))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // the user sees who is in their account
+    let sessions: Value = parent
+        .get(format!("http://{}/api/auth/sessions", addr))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(sessions.as_array().unwrap().len(), 2);
+    assert_eq!(sessions[0]["impersonator"], admin_login);
+    assert_eq!(sessions[0]["impersonator_id"], admin.id.to_string());
+    assert_eq!(sessions[1]["impersonator"], Value::Null);
+
+    let requests: Vec<String> = audit_log::table
+        .filter(audit_log::user_id.eq(admin.id))
+        .filter(audit_log::event.eq("impersonated_request"))
+        .order(audit_log::created_at)
+        .select(audit_log::details)
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(
+        requests,
+        [
+            format!("GET /api/auth/greet as {login}"),
+            format!("POST /api/auth/2fa/setup as {login}"),
+            format!("POST /api/auth/logout-all as {login}"),
+            format!("DELETE /api/auth/sessions/{session} as {login}"),
+        ]
+    );
+
+    let res = support
+        .post(format!("http://{}/api/auth/logout", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let res = support
+        .get(format!("http://{}/api/auth/greet", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,186 @@
+use backend::{administration, auth, models::Role, schema::invitations};
+use diesel::prelude::*;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::{
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+use time::macros::date;
+use uuid::Uuid;
+mod tools;
+
+/// Logs in a school admin of a new school, returns the school and the admin's CSRF token
+async fn school_admin(client: &Client, addr: SocketAddr) -> (Uuid, String) {
+    let login = tools::register_and_login(client, addr).await;
+    let school = administration::create_school(&mut tools::conn(), "Invited", "Lodz", None)
+        .unwrap()
+        .id;
+    tools::set_role(&login, Role::SchoolAdmin, Some(school));
+    (school, tools::csrf_token(client, addr).await)
+}
+
+async fn invite(client: &Client, addr: SocketAddr, csrf: &str, target: Value) -> Value {
+    let res = client
+        .post(format!("http://{}/api/admin/invitation", addr))
+        .header(tools::CSRF_HEADER, csrf)
+        .json(&target)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    res.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn teacher_registers_with_invitation() {
+    let addr = tools::spawn_app().await;
+    let admin = tools::client();
+    let (school, csrf) = school_admin(&admin, addr).await;
+
+    // the record comes first, without an account
+    let res = admin
+        .post(format!("http://{}/api/admin/teacher", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "first_name": "Anna", "last_name": "Nowak", "school_id": school }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let teacher: Value = res.json().await.unwrap();
+    assert_eq!(teacher["user_id"], Value::Null);
+
+    let invitation = invite(&admin, addr, &csrf, json!({ "teacher_id": teacher["id"] })).await;
+    let code = invitation["code"].as_str().unwrap();
+    assert!(invitation.get("code_hash").is_none());
+
+    // codes may be typed without dashes and in lowercase
+    let login = format!("test_user_{}", Uuid::new_v4());
+    let res = tools::client()
+        .post(format!("http://{}/api/auth/register", addr))
+        .json(&json!({
+            "login": login,
+            "email": format!("{}@gmail.com", Uuid::new_v4()),
+            "password": tools::PASSWORD,
+            "invitation": code.replace('-', "").to_lowercase(),
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let mut conn = tools::conn();
+    let user = auth::get_by_login(&mut conn, &login).unwrap().unwrap();
+    assert_eq!(user.role, Role::Teacher);
+    assert_eq!(user.school_id, Some(school));
+    let id = teacher["id"].as_str().unwrap().parse().unwrap();
+    let teacher = administration::get_teacher(&mut conn, id).unwrap();
+    assert_eq!(teacher.user_id, Some(user.id));
+
+    // codes are single use and a failed registration leaves no account behind
+    let login = format!("test_user_{}", Uuid::new_v4());
+    let res = tools::client()
+        .post(format!("http://{}/api/auth/register", addr))
+        .json(&json!({
+            "login": login,
+            "email": format!("{}@gmail.com", Uuid::new_v4()),
+            "password": tools::PASSWORD,
+            "invitation": code,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_invitation");
+    assert!(auth::get_by_login(&mut conn, &login).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn student_logs_in_with_invitation() {
+    let addr = tools::spawn_app().await;
+    let admin = tools::client();
+    let (school, csrf) = school_admin(&admin, addr).await;
+    let mut conn = tools::conn();
+    let group = administration::create_group(&mut conn, "1A", school).unwrap();
+    let student = administration::create_student(
+        &mut conn,
+        "Jan",
+        "Kowalski",
+        date!(2010 - 05 - 01),
+        school,
+        group.id,
+        None,
+    )
+    .unwrap();
+
+    let (login, _) = tools::register(&tools::client(), addr).await;
+    let login_with = |code: String| {
+        let login = login.clone();
+        async move {
+            tools::client()
+                .post(format!("http://{}/api/auth/login", addr))
+                .json(&json!({ "login": login, "password": tools::PASSWORD, "invitation": code }))
+                .send()
+                .await
+                .unwrap()
+                .status()
+        }
+    };
+
+    // revoked codes can't be redeemed
+    let revoked = invite(&admin, addr, &csrf, json!({ "student_id": student.id })).await;
+    let res = admin
+        .delete(format!(
+            "http://{}/api/admin/invitation/{}",
+            addr,
+            revoked["id"].as_str().unwrap()
+        ))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let code = revoked["code"].as_str().unwrap().to_string();
+    assert_eq!(login_with(code).await, StatusCode::BAD_REQUEST);
+
+    // neither can expired ones
+    let expired = invite(&admin, addr, &csrf, json!({ "student_id": student.id })).await;
+    let expired_id: Uuid = expired["id"].as_str().unwrap().parse().unwrap();
+    diesel::update(invitations::table.find(expired_id))
+        .set(invitations::expires_at.eq(SystemTime::now() - Duration::from_secs(60)))
+        .execute(&mut conn)
+        .unwrap();
+    let code = expired["code"].as_str().unwrap().to_string();
+    assert_eq!(login_with(code).await, StatusCode::BAD_REQUEST);
+
+    let valid = invite(&admin, addr, &csrf, json!({ "student_id": student.id })).await;
+    let code = valid["code"].as_str().unwrap().to_string();
+    assert_eq!(login_with(code).await, StatusCode::OK);
+
+    let user = auth::get_by_login(&mut conn, &login).unwrap().unwrap();
+    let student = administration::get_student(&mut conn, student.id).unwrap();
+    assert_eq!(student.user_id, Some(user.id));
+    assert_eq!(user.school_id, Some(school));
+
+    // linked records get no further invitations
+    let res = admin
+        .post(format!("http://{}/api/admin/invitation", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "student_id": student.id }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    let invitations: Value = admin
+        .get(format!("http://{}/api/admin/invitation", addr))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(invitations.as_array().unwrap().len(), 3);
+    assert!(invitations[0]["redeemed_at"].is_object());
+}
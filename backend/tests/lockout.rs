@@ -0,0 +1,126 @@
+use backend::{models::AuditEntry, schema::audit_log};
+use diesel::prelude::*;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use uuid::Uuid;
+mod tools;
+
+async fn login(
+    client: &Client,
+    addr: SocketAddr,
+    login: &str,
+    password: &str,
+) -> (StatusCode, Value) {
+    let res = client
+        .post(format!("http://{}/api/auth/login", addr))
+        .json(&json!({ "login": login, "password": password }))
+        .send()
+        .await
+        .unwrap();
+    (res.status(), res.json().await.unwrap_or_default())
+}
+
+fn audit_entries(event: &str, details: &str) -> Vec<AuditEntry> {
+    audit_log::table
+        .filter(audit_log::event.eq(event))
+        .filter(audit_log::details.like(format!("%{details}%")))
+        .load(&mut tools::conn())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn unknown_login_looks_like_wrong_password() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let (user_login, _) = tools::register(&client, addr).await;
+
+    let wrong_password = login(&client, addr, &user_login, "wrong_password").await;
+    let unknown_login = login(&client, addr, &Uuid::new_v4().to_string(), "wrong_password").await;
+
+    assert_eq!(wrong_password.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(wrong_password, unknown_login);
+    assert_eq!(wrong_password.1["code"], "invalid_credentials");
+}
+
+#[tokio::test]
+async fn account_gets_locked() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let (user_login, _) = tools::register(&client, addr).await;
+
+    for _ in 0..5 {
+        let (status, _) = login(&client, addr, &user_login, "wrong_password").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    // even the right password from another address is refused
+    let (status, body) = login(&tools::client(), addr, &user_login, tools::PASSWORD).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["code"], "too_many_attempts");
+
+    let entries = audit_entries("account_locked", &user_login);
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].user_id.is_some());
+}
+
+#[tokio::test]
+async fn address_gets_locked() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let (user_login, _) = tools::register(&tools::client(), addr).await;
+
+    for _ in 0..20 {
+        let (status, _) = login(&client, addr, &Uuid::new_v4().to_string(), "wrong_password").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    let (status, _) = login(&client, addr, &user_login, tools::PASSWORD).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // the account itself isn't locked
+    let (status, _) = login(&tools::client(), addr, &user_login, tools::PASSWORD).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn profile_changes_count_towards_lockout() {
+    let addr = tools::spawn_app().await;
+    let client = tools::client();
+    let user_login = tools::register_and_login(&client, addr).await;
+    let csrf = tools::csrf_token(&client, addr).await;
+
+    for _ in 0..3 {
+        let res = client
+            .patch(format!("http://{}/api/auth/profile", addr))
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({ "password": "wrong_password", "login": Uuid::new_v4() }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+    for _ in 0..2 {
+        let res = client
+            .delete(format!("http://{}/api/auth/profile", addr))
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({ "password": "wrong_password" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // a stolen session can't keep guessing either
+    let res = client
+        .delete(format!("http://{}/api/auth/profile", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "password": tools::PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let (status, _) = login(&tools::client(), addr, &user_login, tools::PASSWORD).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
@@ -0,0 +1,340 @@
+use axum::{
+    extract::{Form, Query},
+    response::Redirect,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use backend::{
+    auth::AuthError,
+    config::PasswordConfig,
+    oidc::{self, Claims},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use openssl::rsa::Rsa;
+use reqwest::{redirect::Policy, Client, StatusCode, Url};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+mod tools;
+
+const CLIENT_ID: &str = "bibrus";
+
+/// Identity provider serving discovery, JWKS, authorization and token endpoints.
+/// Instead of a login form, its authorization endpoint takes the user's claims as parameters.
+struct MockIdp {
+    issuer: String,
+    key: EncodingKey,
+    jwks: Value,
+    grants: Mutex<HashMap<String, Grant>>,
+}
+
+struct Grant {
+    redirect_uri: String,
+    code_challenge: String,
+    claims: Value,
+}
+
+#[derive(Deserialize)]
+struct Authorize {
+    client_id: String,
+    redirect_uri: String,
+    state: String,
+    nonce: String,
+    code_challenge: String,
+    code_challenge_method: String,
+    sub: String,
+    email: String,
+    email_verified: bool,
+    preferred_username: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: String,
+    redirect_uri: String,
+    client_id: String,
+    code_verifier: String,
+}
+
+async fn discovery(Extension(idp): Extension<Arc<MockIdp>>) -> Json<Value> {
+    Json(json!({
+        "issuer": idp.issuer,
+        "authorization_endpoint": format!("{}/authorize", idp.issuer),
+        "token_endpoint": format!("{}/token", idp.issuer),
+        "jwks_uri": format!("{}/jwks", idp.issuer),
+    }))
+}
+
+async fn jwks(Extension(idp): Extension<Arc<MockIdp>>) -> Json<Value> {
+    Json(idp.jwks.clone())
+}
+
+async fn authorize(
+    Query(query): Query<Authorize>,
+    Extension(idp): Extension<Arc<MockIdp>>,
+) -> Redirect {
+    assert_eq!(query.client_id, CLIENT_ID);
+    assert_eq!(query.code_challenge_method, "S256");
+
+    let code = Uuid::new_v4().to_string();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let claims = json!({
+        "iss": idp.issuer,
+        "aud": CLIENT_ID,
+        "sub": query.sub,
+        "email": query.email,
+        "email_verified": query.email_verified,
+        "preferred_username": query.preferred_username,
+        "nonce": query.nonce,
+        "iat": now,
+        "exp": now + 300,
+    });
+    let url = format!("{}?code={}&state={}", query.redirect_uri, code, query.state);
+    idp.grants.lock().unwrap().insert(
+        code,
+        Grant {
+            redirect_uri: query.redirect_uri,
+            code_challenge: query.code_challenge,
+            claims,
+        },
+    );
+    Redirect::to(&url)
+}
+
+async fn token(
+    Form(form): Form<TokenRequest>,
+    Extension(idp): Extension<Arc<MockIdp>>,
+) -> Result<Json<Value>, StatusCode> {
+    // codes are single use
+    let grant = idp
+        .grants
+        .lock()
+        .unwrap()
+        .remove(&form.code)
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(form.code_verifier.as_bytes()));
+    if form.grant_type != "authorization_code"
+        || form.client_id != CLIENT_ID
+        || form.redirect_uri != grant.redirect_uri
+        || challenge != grant.code_challenge
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut header = Header::new(jsonwebtoken::Algorithm::RS256);
+    header.kid = Some("test-key".to_string());
+    let id_token = encode(&header, &grant.claims, &idp.key).unwrap();
+    Ok(Json(
+        json!({ "access_token": "unused", "token_type": "Bearer", "id_token": id_token }),
+    ))
+}
+
+/// Starts the provider once for all tests of this file, returns its issuer URL
+fn mock_idp() -> &'static str {
+    static ISSUER: OnceLock<String> = OnceLock::new();
+    ISSUER.get_or_init(|| {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let rsa = Rsa::generate(2048).unwrap();
+        let idp = Arc::new(MockIdp {
+            issuer: issuer.clone(),
+            key: EncodingKey::from_rsa_pem(&rsa.private_key_to_pem().unwrap()).unwrap(),
+            jwks: json!({ "keys": [{
+                "kty": "RSA",
+                "use": "sig",
+                "alg": "RS256",
+                "kid": "test-key",
+                "n": URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+                "e": URL_SAFE_NO_PAD.encode(rsa.e().to_vec()),
+            }]}),
+            grants: Mutex::new(HashMap::new()),
+        });
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/authorize", get(authorize))
+            .route("/token", post(token))
+            .layer(Extension(idp));
+
+        // tests have their own runtimes, the provider outlives each of them
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(async move {
+                    axum::Server::from_tcp(listener)
+                        .unwrap()
+                        .serve(app.into_make_service())
+                        .await
+                        .unwrap();
+                })
+        });
+        issuer
+    })
+}
+
+async fn spawn_app() -> SocketAddr {
+    std::env::set_var("OIDC_ISSUER_URL", mock_idp());
+    std::env::set_var("OIDC_CLIENT_ID", CLIENT_ID);
+    std::env::set_var("OIDC_PROVISION_USERS", "true");
+    tools::spawn_app().await
+}
+
+fn client() -> Client {
+    Client::builder()
+        .cookie_store(true)
+        .redirect(Policy::none())
+        .build()
+        .unwrap()
+}
+
+/// Starts a login and lets the provider authenticate the user with `claims`,
+/// returns the code and state the provider sends back
+async fn authorize_at_idp(client: &Client, addr: SocketAddr, claims: &str) -> (String, String) {
+    let res = client
+        .get(format!("http://{}/api/auth/oidc/login", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    let location = res.headers()["location"].to_str().unwrap().to_string();
+    assert!(location.starts_with(mock_idp()));
+
+    let res = client
+        .get(format!("{location}&{claims}"))
+        .send()
+        .await
+        .unwrap();
+    let callback = Url::parse(res.headers()["location"].to_str().unwrap()).unwrap();
+    assert_eq!(callback.path(), "/api/auth/oidc/callback");
+    let params: HashMap<_, _> = callback.query_pairs().into_owned().collect();
+    (params["code"].clone(), params["state"].clone())
+}
+
+async fn callback(client: &Client, addr: SocketAddr, code: &str, state: &str) -> reqwest::Response {
+    client
+        .get(format!("http://{}/api/auth/oidc/callback", addr))
+        .query(&[("code", code), ("state", state)])
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Logs in through the provider, returns the response of the callback
+async fn sso_login(client: &Client, addr: SocketAddr, claims: &str) -> reqwest::Response {
+    let (code, state) = authorize_at_idp(client, addr, claims).await;
+    callback(client, addr, &code, &state).await
+}
+
+async fn greet(client: &Client, addr: SocketAddr) -> reqwest::Response {
+    client
+        .get(format!("http://{}/api/auth/greet", addr))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn provisions_and_links_users() {
+    let addr = spawn_app().await;
+
+    // unknown users get an account
+    let sub = Uuid::new_v4();
+    let username = format!("sso_{}", sub.simple());
+    let claims = format!(
+        "sub={sub}&email={}@school.edu&email_verified=true&preferred_username={username}",
+        Uuid::new_v4()
+    );
+    let provisioned = client();
+    let res = sso_login(&provisioned, addr, &claims).await;
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    assert_eq!(res.headers()["location"], "http://localhost:3000");
+    let res = greet(&provisioned, addr).await;
+    assert_eq!(res.text().await.unwrap(), format!("Hello {username}"));
+
+    // the subject stays linked to the same account
+    let again = client();
+    sso_login(&again, addr, &claims).await;
+    let res = greet(&again, addr).await;
+    assert_eq!(res.text().await.unwrap(), format!("Hello {username}"));
+
+    // existing users are linked by their verified email
+    let (login, email) = tools::register(&tools::client(), addr).await;
+    let linked = client();
+    let claims = format!("sub={}&email={email}&email_verified=true", Uuid::new_v4());
+    let res = sso_login(&linked, addr, &claims).await;
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    let res = greet(&linked, addr).await;
+    assert_eq!(res.text().await.unwrap(), format!("Hello {login}"));
+
+    // but not by an address the provider didn't verify
+    let unverified = client();
+    let claims = format!("sub={}&email={email}&email_verified=false", Uuid::new_v4());
+    let res = sso_login(&unverified, addr, &claims).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "external_login_failed");
+    let res = greet(&unverified, addr).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // without provisioning unknown users are refused the same way
+    let claims: Claims = serde_json::from_value(json!({
+        "sub": Uuid::new_v4().to_string(),
+        "email": format!("{}@school.edu", Uuid::new_v4()),
+        "email_verified": true,
+    }))
+    .unwrap();
+    let result = oidc::find_or_create_user(
+        &mut tools::conn(),
+        mock_idp(),
+        &claims,
+        false,
+        &PasswordConfig::from_env(),
+    );
+    assert!(matches!(result, Err(AuthError::ExternalLoginFailed)));
+}
+
+#[tokio::test]
+async fn callback_checks_state_and_code_verifier() {
+    let addr = spawn_app().await;
+    let claims = format!(
+        "sub={}&email={}@school.edu&email_verified=true",
+        Uuid::new_v4(),
+        Uuid::new_v4()
+    );
+
+    let victim = client();
+    let (code, state) = authorize_at_idp(&victim, addr, &claims).await;
+
+    // a code can't be used by a browser which didn't start the login
+    let attacker = client();
+    let res = callback(&attacker, addr, &code, &state).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "external_login_failed");
+
+    // nor with the state of another login, its PKCE verifier doesn't match the code
+    let (_, attacker_state) = authorize_at_idp(&attacker, addr, &claims).await;
+    let res = callback(&attacker, addr, &code, &attacker_state).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res = greet(&attacker, addr).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let (code, state) = authorize_at_idp(&victim, addr, &claims).await;
+    let res = callback(&victim, addr, &code, &state).await;
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    let res = greet(&victim, addr).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
@@ -0,0 +1,58 @@
+use backend::{
+    auth,
+    config::{PasswordConfig, SessionConfig},
+    database,
+    schema::sessions,
+    session_reaper,
+};
+use diesel::prelude::*;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use uuid::Uuid;
+mod tools;
+
+#[tokio::test]
+async fn reaper_deletes_expired_sessions() {
+    let mut conn = tools::conn();
+    let login = format!("test_user_{}", Uuid::new_v4());
+    let email = format!("{}@gmail.com", Uuid::new_v4());
+    auth::try_create_new_user(
+        &mut conn,
+        &login,
+        &email,
+        tools::PASSWORD,
+        &PasswordConfig::from_env(),
+    )
+    .unwrap();
+    let user = auth::get_by_login(&mut conn, &login).unwrap().unwrap();
+
+    let expired = auth::create_session(&mut conn, user.id, false).unwrap();
+    let active = auth::create_session(&mut conn, user.id, false).unwrap();
+    diesel::update(sessions::table.find(expired))
+        .set(sessions::last_seen.eq(SystemTime::now() - Duration::from_secs(24 * 60 * 60)))
+        .execute(&mut conn)
+        .unwrap();
+
+    let config = SessionConfig {
+        reaper_interval: Duration::from_millis(10),
+        reaper_batch_size: 1,
+        ..SessionConfig::from_env()
+    };
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    let reaper = session_reaper::spawn(database::get_connection_pool(), config, shutdown_rx);
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let remaining = sessions::table
+        .select(sessions::id)
+        .filter(sessions::user_id.eq(user.id))
+        .load::<Uuid>(&mut conn)
+        .unwrap();
+    assert_eq!(remaining, vec![active]);
+
+    shutdown_tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), reaper)
+        .await
+        .expect("Reaper did not stop")
+        .unwrap();
+}
@@ -0,0 +1,193 @@
+use backend::{
+    administration, auth, guardians,
+    models::{NewGuardian, Relationship, Role, Student},
+};
+use reqwest::StatusCode;
+use serde_json::Value;
+use time::macros::date;
+use uuid::Uuid;
+mod tools;
+
+#[tokio::test]
+async fn averages_for_students_guardians_and_teachers() {
+    let addr = tools::spawn_app().await;
+    let mut conn = tools::conn();
+    let school = administration::create_school(&mut conn, "Counted", "Lublin", None).unwrap();
+    let group = administration::create_group(&mut conn, "1E", school.id).unwrap();
+    let math = administration::create_subject(&mut conn, "Math", school.id).unwrap();
+    let art = administration::create_subject(&mut conn, "Art", school.id).unwrap();
+
+    let teacher_client = tools::client();
+    let teacher_login = tools::register_and_login(&teacher_client, addr).await;
+    tools::set_role(&teacher_login, Role::Teacher, Some(school.id));
+    let teacher_user = auth::get_by_login(&mut conn, &teacher_login)
+        .unwrap()
+        .unwrap();
+    let teacher =
+        administration::create_teacher(&mut conn, "Olga", "Sowa", Some(teacher_user.id), school.id)
+            .unwrap();
+    let class = administration::create_class(&mut conn, math.id, group.id, teacher.id).unwrap();
+
+    let student_client = tools::client();
+    let student_login = tools::register_and_login(&student_client, addr).await;
+    let student_user = auth::get_by_login(&mut conn, &student_login)
+        .unwrap()
+        .unwrap();
+
+    // weighted averages are 4.5, 2 and 4.5, marks which don't count are left out
+    let grades = [
+        vec![("5", 3), ("3", 1), ("np", 1)],
+        vec![("2", 2)],
+        vec![("4+", 1), ("+", 1)],
+    ];
+    let mut students = Vec::new();
+    for (i, grades) in grades.iter().enumerate() {
+        let student = administration::create_student(
+            &mut conn,
+            "Kid",
+            &i.to_string(),
+            date!(2014 - 01 - 20),
+            school.id,
+            group.id,
+            (i == 0).then_some(student_user.id),
+        )
+        .unwrap();
+        administration::add_student_to_class(&mut conn, student.id, class.id).unwrap();
+        for (symbol, weight) in grades {
+            let task = tools::create_task(class.id, "Quiz");
+            administration::create_grade(
+                &mut conn,
+                symbol,
+                Some(*weight),
+                teacher.id,
+                student.id,
+                None,
+                task.id,
+                None,
+                None,
+            )
+            .unwrap();
+        }
+        students.push(student);
+    }
+    let drawing = administration::create_class(&mut conn, art.id, group.id, teacher.id).unwrap();
+    administration::add_student_to_class(&mut conn, students[0].id, drawing.id).unwrap();
+    let task = tools::create_task(drawing.id, "Drawing");
+    administration::create_grade(
+        &mut conn,
+        "bz",
+        Some(1),
+        teacher.id,
+        students[0].id,
+        Some(art.id),
+        task.id,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let summary_url =
+        |student: &Student| format!("http://{}/api/summary/student/{}", addr, student.id);
+    let res = student_client
+        .get(summary_url(&students[0]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let averages: Value = res.json().await.unwrap();
+    assert_eq!(averages[0]["subject_name"], "Art");
+    assert_eq!(averages[0]["average"], Value::Null);
+    assert_eq!(averages[1]["subject_name"], "Math");
+    assert_eq!(averages[1]["average"], 4.5);
+    assert_eq!(averages[1]["grades"], 2);
+
+    let res = student_client
+        .get(summary_url(&students[1]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = student_client
+        .get(format!(
+            "http://{}/api/summary/student/{}",
+            addr,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let parent = tools::client();
+    let parent_login = tools::register_and_login(&parent, addr).await;
+    let parent_user = auth::get_by_login(&mut conn, &parent_login)
+        .unwrap()
+        .unwrap();
+    guardians::add_guardian(
+        &mut conn,
+        &NewGuardian {
+            user_id: parent_user.id,
+            student_id: students[1].id,
+            relationship: Relationship::Father,
+            primary_contact: true,
+            emergency_contact: false,
+        },
+    )
+    .unwrap();
+    let averages: Value = parent
+        .get(summary_url(&students[1]))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(averages[0]["average"], 2.0);
+
+    let class_url = format!("http://{}/api/summary/class/{}", addr, class.id);
+    let res = teacher_client.get(&class_url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let summary: Value = res.json().await.unwrap();
+    assert!((summary["mean"].as_f64().unwrap() - 11.0 / 3.0).abs() < 1e-9);
+    assert_eq!(summary["median"], 4.5);
+    let distribution: Vec<(&str, u64)> = summary["distribution"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            (
+                entry["symbol"].as_str().unwrap(),
+                entry["count"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(distribution, [("2", 1), ("3", 1), ("4+", 1), ("5", 1)]);
+    assert_eq!(summary["students"].as_array().unwrap().len(), 3);
+
+    // other teachers of the school don't see the class
+    let other = tools::client();
+    let other_login = tools::register_and_login(&other, addr).await;
+    tools::set_role(&other_login, Role::Teacher, Some(school.id));
+    let res = other.get(&class_url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = parent.get(&class_url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // and unknown classes look like other schools' classes
+    let elsewhere = administration::create_school(&mut conn, "Elsewhere", "Opole", None).unwrap();
+    let stranger = tools::client();
+    let stranger_login = tools::register_and_login(&stranger, addr).await;
+    tools::set_role(&stranger_login, Role::Teacher, Some(elsewhere.id));
+    let res = stranger.get(&class_url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = stranger
+        .get(format!(
+            "http://{}/api/summary/class/{}",
+            addr,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
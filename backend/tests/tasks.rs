@@ -0,0 +1,270 @@
+use backend::{administration, models::Role};
+use diesel::{sql_query, RunQueryDsl};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use time::macros::date;
+use uuid::Uuid;
+mod tools;
+
+#[tokio::test]
+async fn grades_inherit_subject_and_weight_from_their_task() {
+    let addr = tools::spawn_app().await;
+    let mut conn = tools::conn();
+    let school = administration::create_school(&mut conn, "Tasked", "Sopot", None).unwrap();
+    let group = administration::create_group(&mut conn, "6F", school.id).unwrap();
+    let subject = administration::create_subject(&mut conn, "Biology", school.id).unwrap();
+    let teacher =
+        administration::create_teacher(&mut conn, "Anna", "Wilk", None, school.id).unwrap();
+    let class = administration::create_class(&mut conn, subject.id, group.id, teacher.id).unwrap();
+    let student = administration::create_student(
+        &mut conn,
+        "Jakub",
+        "Mak",
+        date!(2010 - 05 - 02),
+        school.id,
+        group.id,
+        None,
+    )
+    .unwrap();
+    administration::add_student_to_class(&mut conn, student.id, class.id).unwrap();
+
+    let client = tools::client();
+    let login = tools::register_and_login(&client, addr).await;
+    tools::set_role(&login, Role::Teacher, Some(school.id));
+    let csrf = tools::csrf_token(&client, addr).await;
+
+    let date = serde_json::to_value(date!(2023 - 02 - 20)).unwrap();
+    let res = client
+        .post(format!("http://{}/api/admin/task", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({
+            "name": "Cells",
+            "class_id": class.id,
+            "category": "quiz",
+            "date": date,
+            "max_points": 20.0,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let task: Value = res.json().await.unwrap();
+    assert_eq!(task["category"], "quiz");
+    assert_eq!(task["weight"], 2);
+    assert_eq!(task["date"], date);
+    assert_eq!(task["description"], Value::Null);
+    assert_eq!(task["max_points"], 20.0);
+
+    let post_grade = |grade: Value| {
+        client
+            .post(format!("http://{}/api/admin/grade", addr))
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&grade)
+            .send()
+    };
+    let res = post_grade(json!({
+        "symbol": "4",
+        "teacher_id": teacher.id,
+        "student_id": student.id,
+        "task_id": task["id"],
+    }))
+    .await
+    .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let grade: Value = res.json().await.unwrap();
+    assert_eq!(grade["subject_id"], subject.id.to_string());
+    assert_eq!(grade["weight"], 2);
+
+    let res = post_grade(json!({
+        "symbol": "5",
+        "weight": 4,
+        "teacher_id": teacher.id,
+        "student_id": student.id,
+        "task_id": task["id"],
+        "corrects_id": grade["id"],
+    }))
+    .await
+    .unwrap();
+    let retake: Value = res.json().await.unwrap();
+    assert_eq!(retake["weight"], 4);
+
+    // grades of a class's task stay within the class
+    let chemistry = administration::create_subject(&mut conn, "Chemistry", school.id).unwrap();
+    let outsider = administration::create_student(
+        &mut conn,
+        "Iga",
+        "Sowa",
+        date!(2010 - 07 - 12),
+        school.id,
+        group.id,
+        None,
+    )
+    .unwrap();
+    for (student_id, subject_id) in [(student.id, chemistry.id), (outsider.id, subject.id)] {
+        let res = post_grade(json!({
+            "symbol": "3",
+            "teacher_id": teacher.id,
+            "student_id": student_id,
+            "subject_id": subject_id,
+            "task_id": task["id"],
+        }))
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: Value = res.json().await.unwrap();
+        assert_eq!(body["code"], "not_in_class");
+    }
+
+    // grades given earlier keep their weight
+    let task_url = format!(
+        "http://{}/api/admin/task/{}",
+        addr,
+        task["id"].as_str().unwrap()
+    );
+    let res = client
+        .patch(&task_url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "weight": 1, "description": "Chapter 3" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let task: Value = res.json().await.unwrap();
+    assert_eq!(task["weight"], 1);
+    assert_eq!(task["description"], "Chapter 3");
+    let grade_url = format!(
+        "http://{}/api/admin/grade/{}",
+        addr,
+        grade["id"].as_str().unwrap()
+    );
+    let grade: Value = client
+        .get(&grade_url)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(grade["weight"], 2);
+
+    // weights below 1 would break the weighted averages
+    let res = post_grade(json!({
+        "symbol": "4",
+        "weight": 0,
+        "teacher_id": teacher.id,
+        "student_id": student.id,
+        "task_id": task["id"],
+    }))
+    .await
+    .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_weight");
+    assert_eq!(body["fields"][0]["field"], "weight");
+    let res = client
+        .patch(&grade_url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "weight": -1, "reason": "Typo" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let tasks: Value = client
+        .get(format!(
+            "http://{}/api/admin/task?class_id={}",
+            addr, class.id
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(tasks.as_array().unwrap().len(), 1);
+
+    // tasks from before tasks belonged to classes have no school, only system admins reach them
+    let legacy = Uuid::new_v4();
+    sql_query(format!(
+        "insert into tasks (id, name, category, weight) values ('{legacy}', 'Old', 'test', 1)"
+    ))
+    .execute(&mut conn)
+    .unwrap();
+    let legacy_grade = json!({
+        "symbol": "3",
+        "teacher_id": teacher.id,
+        "student_id": student.id,
+        "task_id": legacy,
+    });
+    let res = post_grade(legacy_grade.clone()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let legacy_url = format!("http://{}/api/admin/task/{}", addr, legacy);
+    let res = client.get(&legacy_url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = client
+        .patch(&legacy_url)
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "name": "Mine now" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let tasks: Value = client
+        .get(format!("http://{}/api/admin/task", addr))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(tasks.as_array().unwrap().len(), 1);
+
+    // and they have no subject to give
+    let admin = tools::client();
+    let admin_login = tools::register_and_login(&admin, addr).await;
+    tools::set_role(&admin_login, Role::SystemAdmin, None);
+    let res = admin
+        .post(format!("http://{}/api/admin/grade", addr))
+        .header(tools::CSRF_HEADER, tools::csrf_token(&admin, addr).await)
+        .json(&legacy_grade)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "subject_required");
+
+    // classes of other schools are out of reach
+    let elsewhere = administration::create_school(&mut conn, "Elsewhere", "Sopot", None).unwrap();
+    let other_group = administration::create_group(&mut conn, "1A", elsewhere.id).unwrap();
+    let other_subject = administration::create_subject(&mut conn, "Art", elsewhere.id).unwrap();
+    let other_teacher =
+        administration::create_teacher(&mut conn, "Jan", "Mak", None, elsewhere.id).unwrap();
+    let other_class = administration::create_class(
+        &mut conn,
+        other_subject.id,
+        other_group.id,
+        other_teacher.id,
+    )
+    .unwrap();
+    let res = client
+        .post(format!("http://{}/api/admin/task", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({
+            "name": "Portrait",
+            "class_id": other_class.id,
+            "category": "homework",
+            "date": date,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let other_task = tools::create_task(other_class.id, "Portrait");
+    let res = client
+        .get(format!("http://{}/api/admin/task/{}", addr, other_task.id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
@@ -1,22 +1,160 @@
-use ::backend::app;
-use reqwest::Client;
-use std::net::{SocketAddr, TcpListener};
+#![allow(dead_code)]
+use ::backend::{
+    administration, app, auth, database,
+    models::{NewTask, Role, Task, TaskCategory},
+    routes::csrf,
+};
+use reqwest::{header::HeaderMap, Client, StatusCode};
+use serde_json::json;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use time::macros::date;
+use uuid::Uuid;
 
+/// Client with a cookie store and its own made up address, so that lockouts of one test
+/// don't affect the others
 pub fn client() -> Client {
-    Client::builder().cookie_store(true).build().unwrap()
+    let ip = Ipv4Addr::from(rand::random::<u32>());
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", ip.to_string().parse().unwrap());
+
+    Client::builder()
+        .cookie_store(true)
+        .default_headers(headers)
+        .build()
+        .unwrap()
 }
 
 pub async fn spawn_app() -> SocketAddr {
-    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 3000))).unwrap();
+    std::env::set_var("TRUST_PROXY", "true");
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
     let addr = listener.local_addr().unwrap();
 
+    // reads the configuration now, tests may change it afterwards
+    let app = app();
     tokio::spawn(async move {
         axum::Server::from_tcp(listener)
             .unwrap()
-            .serve(app().into_make_service())
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
             .await
             .unwrap();
     });
 
     addr
 }
+
+pub fn conn() -> auth::PgConn {
+    dotenv::dotenv().ok();
+    database::get_connection_pool().get().unwrap()
+}
+
+pub const PASSWORD: &str = "strong_pass12345";
+pub const CSRF_HEADER: &str = csrf::CSRF_HEADER;
+
+/// Registers a new user and confirms their email, returns the user's login and email
+pub async fn register(client: &Client, addr: SocketAddr) -> (String, String) {
+    let login = format!("test_user_{}", Uuid::new_v4());
+    let email = format!("{}@gmail.com", Uuid::new_v4());
+    let payload = json!({
+        "login": login,
+        "email": email,
+        "password": PASSWORD,
+    });
+
+    let res = client
+        .post(format!("http://{}/api/auth/register", addr))
+        .json(&payload)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .post(format!("http://{}/api/auth/verify-email", addr))
+        .json(&json!({ "token": mailed_token(&email, "verify-email") }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    (login, email)
+}
+
+/// Registers a new user and logs `client` in as them, returns the user's login
+pub async fn register_and_login(client: &Client, addr: SocketAddr) -> String {
+    let (login, _) = register(client, addr).await;
+    login_as(client, addr, &login).await;
+    login
+}
+
+pub async fn login_as(client: &Client, addr: SocketAddr, login: &str) {
+    let res = client
+        .post(format!("http://{}/api/auth/login", addr))
+        .json(&json!({ "login": login, "email": "", "password": PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+pub fn set_role(login: &str, role: Role, school_id: Option<Uuid>) {
+    let mut conn = conn();
+    let user = auth::get_by_login(&mut conn, login).unwrap().unwrap();
+    auth::set_role(&mut conn, user.id, role, school_id).unwrap();
+}
+
+/// Test in the class with the category's weight
+pub fn create_task(class_id: Uuid, name: &str) -> Task {
+    administration::create_task(
+        &mut conn(),
+        &NewTask {
+            name,
+            class_id,
+            category: TaskCategory::Test,
+            weight: TaskCategory::Test.default_weight(),
+            date: date!(2022 - 09 - 05),
+            description: None,
+            max_points: None,
+        },
+    )
+    .unwrap()
+}
+
+/// Finds the newest mail sent to `email` by the file mailer and returns the token from its `link`.
+/// Some mail is sent in the background, so it waits for it a moment.
+pub fn mailed_token(email: &str, link: &str) -> String {
+    let to = format!("To: {email}\n");
+    let link = format!("/{link}?token=");
+    let mut mails = Vec::new();
+    for _ in 0..50 {
+        mails = std::fs::read_dir("outbox")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                let mail = std::fs::read_to_string(path).unwrap();
+                mail.contains(&to) && mail.contains(&link)
+            })
+            .collect();
+        if !mails.is_empty() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    mails.sort();
+
+    let mail = std::fs::read_to_string(mails.last().expect("No mail was sent")).unwrap();
+    let (_, rest) = mail.split_once(&link).unwrap();
+    rest.split_whitespace().next().unwrap().to_string()
+}
+
+/// Fetches the CSRF token which unsafe requests of a logged in client have to send
+pub async fn csrf_token(client: &Client, addr: SocketAddr) -> String {
+    let body: serde_json::Value = client
+        .get(format!("http://{}/api/auth/csrf", addr))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    body["token"].as_str().unwrap().to_string()
+}
@@ -0,0 +1,290 @@
+use backend::{
+    administration, auth,
+    invitations::{self, Target},
+    models::Role,
+    schema::pending_logins,
+};
+use diesel::prelude::*;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use time::macros::date;
+use totp_rs::{Algorithm, Secret, TOTP};
+mod tools;
+
+async fn spawn_app() -> SocketAddr {
+    std::env::set_var("TWO_FACTOR_REQUIRED_ROLES", "teacher, school_admin");
+    tools::spawn_app().await
+}
+
+fn totp(secret: &str) -> TOTP {
+    let secret = Secret::Encoded(secret.to_string()).to_bytes().unwrap();
+    TOTP::new_unchecked(Algorithm::SHA1, 6, 1, 30, secret, None, String::new())
+}
+
+fn current_code(secret: &str) -> String {
+    totp(secret).generate_current().unwrap()
+}
+
+/// Code of the following 30 second step, still accepted thanks to the allowed clock skew
+fn next_code(secret: &str) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    totp(secret).generate(now + 30)
+}
+
+/// Enables two-factor authentication for the logged in user, returns the secret and recovery codes
+async fn enable(client: &Client, addr: SocketAddr) -> (String, Vec<String>) {
+    let csrf = tools::csrf_token(client, addr).await;
+    let res = client
+        .post(format!("http://{}/api/auth/2fa/setup", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let enrollment: Value = res.json().await.unwrap();
+    let secret = enrollment["secret"].as_str().unwrap().to_string();
+    assert!(enrollment["otpauth_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/Bibrus:"));
+    assert!(enrollment["qr_svg"].as_str().unwrap().contains("<svg"));
+
+    let res = client
+        .post(format!("http://{}/api/auth/2fa/enable", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "code": current_code(&secret) }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = res.json().await.unwrap();
+    let recovery_codes = body["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_string())
+        .collect();
+
+    (secret, recovery_codes)
+}
+
+/// Logs in with password and returns the pending login id
+async fn login(client: &Client, addr: SocketAddr, login: &str) -> Value {
+    let csrf = tools::csrf_token(client, addr).await;
+    let res = client
+        .post(format!("http://{}/api/auth/login", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "login": login, "password": tools::PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let body: Value = res.json().await.unwrap();
+    body["pending_login"].clone()
+}
+
+#[tokio::test]
+async fn login_requires_second_factor() {
+    let addr = spawn_app().await;
+    let client = tools::client();
+    let user_login = tools::register_and_login(&client, addr).await;
+    let csrf = tools::csrf_token(&client, addr).await;
+
+    let res = client
+        .post(format!("http://{}/api/auth/2fa/enable", addr))
+        .header(tools::CSRF_HEADER, &csrf)
+        .json(&json!({ "code": "000000" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let (secret, recovery_codes) = enable(&client, addr).await;
+    assert_eq!(recovery_codes.len(), 10);
+
+    let client = tools::client();
+    let pending_login = login(&client, addr, &user_login).await;
+
+    // the password alone gives no session
+    let res = client
+        .get(format!("http://{}/api/auth/greet", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = client
+        .post(format!("http://{}/api/auth/login/2fa", addr))
+        .json(&json!({ "pending_login": pending_login, "code": "not a code" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let code = next_code(&secret);
+    let res = client
+        .post(format!("http://{}/api/auth/login/2fa", addr))
+        .json(&json!({ "pending_login": pending_login, "code": code }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // an accepted code can't be used again
+    let replay = tools::client();
+    let replayed_login = login(&replay, addr, &user_login).await;
+    let res = replay
+        .post(format!("http://{}/api/auth/login/2fa", addr))
+        .json(&json!({ "pending_login": replayed_login, "code": code }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = client
+        .get(format!("http://{}/api/auth/greet", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // recovery codes work once
+    let csrf = tools::csrf_token(&client, addr).await;
+    for expected in [StatusCode::OK, StatusCode::UNAUTHORIZED] {
+        let pending_login = login(&client, addr, &user_login).await;
+        let res = client
+            .post(format!("http://{}/api/auth/login/2fa", addr))
+            .header(tools::CSRF_HEADER, &csrf)
+            .json(&json!({ "pending_login": pending_login, "code": recovery_codes[0] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), expected);
+    }
+}
+
+#[tokio::test]
+async fn policy_requires_two_factor_for_staff() {
+    let addr = spawn_app().await;
+    let client = tools::client();
+    let user_login = tools::register_and_login(&client, addr).await;
+    let school = administration::create_school(&mut tools::conn(), "Secured", "Elk", None)
+        .unwrap()
+        .id;
+    tools::set_role(&user_login, Role::Teacher, Some(school));
+
+    let res = client
+        .get(format!("http://{}/api/admin/task", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["code"], "two_factor_required");
+
+    enable(&client, addr).await;
+
+    let res = client
+        .get(format!("http://{}/api/admin/task", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn invitation_is_redeemed_after_second_factor() {
+    let addr = spawn_app().await;
+    let client = tools::client();
+    let user_login = tools::register_and_login(&client, addr).await;
+    let (secret, _) = enable(&client, addr).await;
+
+    let mut conn = tools::conn();
+    let user = auth::get_by_login(&mut conn, &user_login).unwrap().unwrap();
+    let school = administration::create_school(&mut conn, "Pending", "Elk", None)
+        .unwrap()
+        .id;
+    let group = administration::create_group(&mut conn, "2B", school).unwrap();
+    let student = administration::create_student(
+        &mut conn,
+        "Ola",
+        "Nowak",
+        date!(2011 - 03 - 04),
+        school,
+        group.id,
+        None,
+    )
+    .unwrap();
+    let (_, code) = invitations::create_invitation(
+        &mut conn,
+        user.id,
+        Target::Student(student.id),
+        time::Duration::days(1),
+    )
+    .unwrap();
+
+    let client = tools::client();
+    let res = client
+        .post(format!("http://{}/api/auth/login", addr))
+        .json(&json!({ "login": user_login, "password": tools::PASSWORD, "invitation": code }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let body: Value = res.json().await.unwrap();
+
+    // only the code's hash waits for the second factor
+    let stored: Option<String> = pending_logins::table
+        .filter(pending_logins::user_id.eq(user.id))
+        .select(pending_logins::invitation_hash)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(stored, Some(invitations::code_hash(&code)));
+
+    // the password alone doesn't link the record
+    let student = administration::get_student(&mut conn, student.id).unwrap();
+    assert_eq!(student.user_id, None);
+
+    let res = client
+        .post(format!("http://{}/api/auth/login/2fa", addr))
+        .json(&json!({ "pending_login": body["pending_login"], "code": next_code(&secret) }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let student = administration::get_student(&mut conn, student.id).unwrap();
+    assert_eq!(student.user_id, Some(user.id));
+}
+
+#[tokio::test]
+async fn wrong_codes_lock_the_account() {
+    let addr = spawn_app().await;
+    let client = tools::client();
+    let user_login = tools::register_and_login(&client, addr).await;
+    enable(&client, addr).await;
+
+    // every guess comes from another address
+    for _ in 0..5 {
+        let client = tools::client();
+        let pending_login = login(&client, addr, &user_login).await;
+        let res = client
+            .post(format!("http://{}/api/auth/login/2fa", addr))
+            .json(&json!({ "pending_login": pending_login, "code": "000000" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let res = tools::client()
+        .post(format!("http://{}/api/auth/login", addr))
+        .json(&json!({ "login": user_login, "password": tools::PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+}